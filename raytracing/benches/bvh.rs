@@ -0,0 +1,98 @@
+use criterion::{Criterion, criterion_group, criterion_main};
+use raytracing::bvh::BvhBuilder;
+use raytracing::camera::Camera;
+use raytracing::interval::Interval;
+use raytracing::material::Lambertian;
+use raytracing::object::Hittable;
+use raytracing::object::Sphere;
+use raytracing::ray::Ray;
+use raytracing::rng::scene_rng;
+use raytracing::scene::Scene;
+use raytracing::texture::SolidColor;
+use raytracing::vec3::{Color, Point3, Vec3};
+use std::hint::black_box;
+use std::sync::Arc;
+
+// Rays from the camera position towards random directions around the look-at point
+fn camera_rays(cam: &Camera, count: usize) -> Vec<Ray> {
+    let mut rng = scene_rng(42);
+    let forward = cam.lookat - cam.lookfrom;
+    (0..count)
+        .map(|_| {
+            let dir = forward + 3.0 * Vec3::random(&mut rng, -1.0..1.0);
+            Ray::new(cam.lookfrom, dir)
+        })
+        .collect()
+}
+
+fn bench_hit(c: &mut Criterion) {
+    let mut cam = Camera::new().unwrap();
+    let world = Scene::Demo.world(&mut cam);
+    let rays = camera_rays(&cam, 10000);
+    let mut rng = scene_rng(0);
+
+    c.bench_function("bvh_hit_demo_scene", |b| {
+        b.iter(|| {
+            for ray in &rays {
+                black_box(world.objects.hit(ray, Interval::new(0.001, f64::INFINITY), &mut rng));
+            }
+        })
+    });
+}
+
+fn bench_build(c: &mut Criterion) {
+    c.bench_function("bvh_build_demo_scene", |b| {
+        b.iter(|| {
+            let mut cam = Camera::new().unwrap();
+            black_box(Scene::Demo.world(&mut cam))
+        })
+    });
+}
+
+// 100k small spheres scattered in a cube. Unlike the demo scene, the tree is deep enough for the traversal to dominate
+fn large_scene() -> Vec<Arc<Sphere<Lambertian<SolidColor>>>> {
+    let mut rng = scene_rng(42);
+    let mat = Lambertian::solid(Color::new(0.5, 0.5, 0.5));
+    (0..100000)
+        .map(|_| Sphere::stationary(Vec3::random(&mut rng, -100.0..100.0), 0.5, mat.clone()))
+        .map(Arc::new)
+        .collect()
+}
+
+fn bench_hit_large(c: &mut Criterion) {
+    let mut builder = BvhBuilder::default();
+    for sphere in large_scene() {
+        builder.add(sphere);
+    }
+    let bvh = builder.build();
+    let mut rng = scene_rng(42);
+    let origin = Point3::new(0.0, 0.0, -250.0);
+    let rays: Vec<_> = (0..10000)
+        .map(|_| Ray::new(origin, Vec3::random(&mut rng, -100.0..100.0) - origin))
+        .collect();
+
+    c.bench_function("bvh_hit_100k_spheres", |b| {
+        b.iter(|| {
+            for ray in &rays {
+                black_box(bvh.hit(ray, Interval::new(0.001, f64::INFINITY), &mut rng));
+            }
+        })
+    });
+}
+
+fn bench_build_large(c: &mut Criterion) {
+    let spheres = large_scene();
+
+    c.bench_function("bvh_build_100k_spheres", |b| {
+        b.iter(|| {
+            let mut builder = BvhBuilder::default();
+            for sphere in &spheres {
+                builder.add(sphere.clone());
+            }
+            black_box(builder.build())
+        })
+    });
+}
+
+criterion_group!(benches, bench_hit, bench_build, bench_hit_large, bench_build_large);
+criterion_main!(benches);
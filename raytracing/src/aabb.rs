@@ -15,7 +15,7 @@ pub enum Axis {
 }
 
 // Struct for Axis-Aligned Bounding Box. See 3.3
-#[derive(Default, Clone)]
+#[derive(Default, Clone, PartialEq)]
 pub struct Aabb {
     x: Interval,
     y: Interval,
@@ -24,7 +24,15 @@ pub struct Aabb {
 
 impl Aabb {
     pub fn from_axis(x: Interval, y: Interval, z: Interval) -> Self {
-        Self { x, y, z }
+        // Adjust the AABB so that no side is narrower than some delta, padding if necessary. Otherwise a flat box
+        // such as the bbox of a sphere with zero radius can never be hit due to floating point errors.
+        const DELTA: f64 = 0.0001;
+        let pad = |i: Interval| if i.len() < DELTA { i.expand(DELTA) } else { i };
+        Self {
+            x: pad(x),
+            y: pad(y),
+            z: pad(z),
+        }
     }
 
     pub fn from_extrema(a: Point3, b: Point3) -> Self {
@@ -45,29 +53,27 @@ impl Aabb {
         Self { x, y, z }
     }
 
-    pub fn hit(&self, ray: &Ray, mut time: Interval) -> bool {
+    // Slab test. This is written without branches so that the compiler can vectorize it. The inverse of the ray
+    // direction is precomputed in `Ray` to avoid divisions on every test.
+    pub fn hit(&self, ray: &Ray, time: Interval) -> bool {
         let orig = ray.origin();
-        let dir = ray.direction();
-        for (ax, dir, orig) in [
-            (self.x, dir.x(), orig.x()),
-            (self.y, dir.y(), orig.y()),
-            (self.z, dir.z(), orig.z()),
+        let inv_dir = ray.inv_direction();
+        let (mut tmin, mut tmax) = (time.min(), time.max());
+        for (ax, dir_inv, orig) in [
+            (self.x, inv_dir.x(), orig.x()),
+            (self.y, inv_dir.y(), orig.y()),
+            (self.z, inv_dir.z(), orig.z()),
         ] {
             // Compute the start/end of bounding box of the axis
-            let dir_inv = 1.0 / dir;
-            let (tmin, tmax) = minmax(
-                (ax.min() - orig) * dir_inv, // t0 = (x0 - Qx) / dx
-                (ax.max() - orig) * dir_inv, // t1 = (x1 - Qx) / dx
-            );
+            let t0 = (ax.min() - orig) * dir_inv; // t0 = (x0 - Qx) / dx
+            let t1 = (ax.max() - orig) * dir_inv; // t1 = (x1 - Qx) / dx
 
-            // Check the intersection of the bounding box and the ray
-            time.lower_bound(tmin);
-            time.upper_bound(tmax);
-            if time.min() >= time.max() {
-                return false;
-            }
+            // Check the intersection of the bounding box and the ray.
+            // Note: `f64::max` and `f64::min` ignore NaN which is made by 0 * inf when the ray is on the slab.
+            tmin = tmin.max(t0.min(t1));
+            tmax = tmax.min(t0.max(t1));
         }
-        true
+        tmin < tmax
     }
 
     pub fn x(&self) -> Interval {
@@ -80,6 +86,11 @@ impl Aabb {
         self.z
     }
 
+    // Whether the box has a finite size. Boxes of unbounded objects such as planes are infinite.
+    pub fn is_bounded(&self) -> bool {
+        [self.x, self.y, self.z].iter().all(|i| i.len().is_finite())
+    }
+
     pub fn longest_axis(&self) -> Axis {
         let (x, y, z) = (self.x.len(), self.y.len(), self.z.len());
         if x >= y {
@@ -94,3 +105,102 @@ impl Aabb {
         (x * y + y * z + z * x) * 2.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+    use proptest::prelude::*;
+
+    // Straightforward version of the slab test with early returns
+    fn hit_scalar(bbox: &Aabb, ray: &Ray, mut time: Interval) -> bool {
+        let (orig, dir) = (ray.origin(), ray.direction());
+        for (ax, dir, orig) in [
+            (bbox.x, dir.x(), orig.x()),
+            (bbox.y, dir.y(), orig.y()),
+            (bbox.z, dir.z(), orig.z()),
+        ] {
+            let (tmin, tmax) = minmax((ax.min() - orig) / dir, (ax.max() - orig) / dir);
+            time.lower_bound(tmin);
+            time.upper_bound(tmax);
+            if time.min() >= time.max() {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn vec3(r: f64) -> impl Strategy<Value = Vec3> {
+        (-r..r, -r..r, -r..r).prop_map(|(x, y, z)| Vec3::new(x, y, z))
+    }
+
+    fn unit_box() -> Aabb {
+        Aabb::from_extrema(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0))
+    }
+
+    const FOREVER: Interval = Interval::new(0.001, f64::INFINITY);
+
+    #[test]
+    fn hit_through_box() {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(unit_box().hit(&ray, FOREVER));
+        let diagonal = Ray::new(Point3::new(-5.0, -5.0, -5.0), Vec3::new(1.0, 1.0, 1.0));
+        assert!(unit_box().hit(&diagonal, FOREVER));
+    }
+
+    #[test]
+    fn hit_misses_box() {
+        let beside = Ray::new(Point3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!unit_box().hit(&beside, FOREVER));
+        let away = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(!unit_box().hit(&away, FOREVER));
+    }
+
+    #[test]
+    fn hit_from_inside() {
+        let ray = Ray::new(Point3::ZERO, Vec3::new(0.3, -0.2, 1.0));
+        assert!(unit_box().hit(&ray, FOREVER));
+    }
+
+    #[test]
+    fn hit_respects_time_interval() {
+        // The box is between t = 4 and t = 6
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!unit_box().hit(&ray, Interval::new(0.001, 3.9)));
+        assert!(unit_box().hit(&ray, Interval::new(0.001, 4.1)));
+        assert!(!unit_box().hit(&ray, Interval::new(6.1, 10.0)));
+    }
+
+    #[test]
+    fn hit_axis_parallel_ray() {
+        // Zero components of the direction make infinite inverses which must not break the slab test
+        let inside = Ray::new(Point3::new(0.5, 0.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(unit_box().hit(&inside, FOREVER));
+        let outside = Ray::new(Point3::new(0.5, 1.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!unit_box().hit(&outside, FOREVER));
+    }
+
+    #[test]
+    fn hit_flat_box() {
+        // Boxes of flat objects are padded so that they can be hit
+        let flat = Aabb::from_extrema(Point3::new(-1.0, 0.0, -1.0), Point3::new(1.0, 0.0, 1.0));
+        let ray = Ray::new(Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(flat.hit(&ray, FOREVER));
+    }
+
+    proptest! {
+        #[test]
+        fn hit_matches_scalar(
+            a in vec3(10.0),
+            b in vec3(10.0),
+            orig in vec3(20.0),
+            dir in vec3(1.0),
+            tmax in 0.1..100.0,
+        ) {
+            let bbox = Aabb::from_extrema(a, b);
+            let ray = Ray::new(orig, dir);
+            let time = Interval::new(0.001, tmax);
+            prop_assert_eq!(bbox.hit(&ray, time), hit_scalar(&bbox, &ray, time));
+        }
+    }
+}
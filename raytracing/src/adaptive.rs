@@ -0,0 +1,55 @@
+use crate::vec3::Color;
+use std::path::PathBuf;
+
+// Options for adaptive sampling. Each pixel traces `min_samples` samples at first and keeps tracing the same number of
+// samples until the estimated noise becomes below the threshold or the count reaches `Camera::samples_per_pixel`.
+pub struct Adaptive {
+    pub min_samples: u32,            // Number of samples traced at once
+    pub threshold: f64,              // Acceptable relative standard error of the pixel luminance
+    pub sample_map: Option<PathBuf>, // Path to write the image of per-pixel sample counts for debugging
+}
+
+impl Default for Adaptive {
+    fn default() -> Self {
+        Self {
+            min_samples: 16,
+            threshold: 0.05,
+            sample_map: None,
+        }
+    }
+}
+
+// Statistics of the samples of a pixel
+#[derive(Default)]
+pub struct PixelStats {
+    pub sum: Color,
+    pub count: u32,
+    lum_sum: f64,
+    lum_sum_sq: f64,
+}
+
+impl PixelStats {
+    pub fn add(&mut self, c: Color) {
+        let l = c.luminance();
+        self.sum += c;
+        self.count += 1;
+        self.lum_sum += l;
+        self.lum_sum_sq += l * l;
+    }
+
+    pub fn mean(&self) -> Color {
+        self.sum / self.count.max(1) as f64
+    }
+
+    // Standard error of the mean luminance relative to the mean. The small constant in the denominator avoids dark
+    // pixels being sampled forever.
+    pub fn relative_error(&self) -> f64 {
+        if self.count < 2 {
+            return f64::INFINITY;
+        }
+        let n = self.count as f64;
+        let mean = self.lum_sum / n;
+        let variance = ((self.lum_sum_sq - self.lum_sum * mean) / (n - 1.0)).max(0.0);
+        (variance / n).sqrt() / (mean + 0.001)
+    }
+}
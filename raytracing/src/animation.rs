@@ -0,0 +1,84 @@
+use crate::transform::Transform;
+use crate::vec3::{Point3, Vec3};
+use std::path::{Path, PathBuf};
+
+// Number of keyframes of the turntable animation
+const TURNTABLE_KEYS: u32 = 36;
+
+// Options for animation rendering. The time of the scene runs from 0 to 1 through the whole animation and frame `i`
+// covers the time [i / frames, (i + 1) / frames]. `Camera::shutter_open` and `Camera::shutter_close` are relative to
+// each frame.
+pub struct Animation {
+    pub frames: u32, // Number of frames to render
+}
+
+// Camera position at a point of time of the animation
+#[derive(Clone, Copy)]
+pub struct Keyframe {
+    pub time: f64,
+    pub lookfrom: Point3,
+    pub lookat: Point3,
+}
+
+impl Keyframe {
+    pub fn new(time: f64, lookfrom: Point3, lookat: Point3) -> Self {
+        Self { time, lookfrom, lookat }
+    }
+}
+
+// Keyframes orbiting `lookfrom` once around `lookat` about the `vup` axis
+pub fn turntable(lookfrom: Point3, lookat: Point3, vup: Vec3) -> Vec<Keyframe> {
+    (0..=TURNTABLE_KEYS)
+        .map(|i| {
+            let t = i as f64 / TURNTABLE_KEYS as f64;
+            let offset = Transform::rotate(vup, 360.0 * t).vector(&(lookfrom - lookat));
+            Keyframe::new(t, lookat + offset, lookat)
+        })
+        .collect()
+}
+
+// Cubic Hermite curve from `p0` to `p1` with the tangents `m0` and `m1`. `t` is in [0, 1]
+fn hermite(p0: Point3, m0: Vec3, p1: Point3, m1: Vec3, t: f64) -> Point3 {
+    let (t2, t3) = (t * t, t * t * t);
+    (2.0 * t3 - 3.0 * t2 + 1.0) * p0 + (t3 - 2.0 * t2 + t) * m0 + (-2.0 * t3 + 3.0 * t2) * p1 + (t3 - t2) * m1
+}
+
+// Camera `lookfrom` and `lookat` at the time. The camera goes through the keyframes along Catmull-Rom splines so that
+// it does not turn abruptly at the keyframes. It stays at the first or last keyframe outside them. The keyframes must
+// be sorted by their time.
+pub fn interpolate(keys: &[Keyframe], time: f64) -> (Point3, Point3) {
+    let idx = keys.partition_point(|k| k.time <= time);
+    if idx == 0 {
+        return (keys[0].lookfrom, keys[0].lookat);
+    }
+    if idx == keys.len() {
+        return (keys[idx - 1].lookfrom, keys[idx - 1].lookat);
+    }
+
+    // Tangents are the slopes between the neighbor keyframes scaled to the length of this segment
+    let (i0, i1) = (idx - 1, idx);
+    let dt = keys[i1].time - keys[i0].time;
+    let tangent = |i: usize, point: fn(&Keyframe) -> Point3| {
+        let (prev, next) = (&keys[i.saturating_sub(1)], &keys[(i + 1).min(keys.len() - 1)]);
+        (point(next) - point(prev)) * (dt / (next.time - prev.time))
+    };
+    let curve = |point: fn(&Keyframe) -> Point3| {
+        let (p0, p1) = (point(&keys[i0]), point(&keys[i1]));
+        hermite(
+            p0,
+            tangent(i0, point),
+            p1,
+            tangent(i1, point),
+            (time - keys[i0].time) / dt,
+        )
+    };
+    (curve(|k| k.lookfrom), curve(|k| k.lookat))
+}
+
+// Path of the numbered image of the frame. For example, "out.ppm" becomes "out.0001.ppm"
+pub fn frame_path(path: &Path, frame: u32) -> PathBuf {
+    match path.extension() {
+        Some(ext) => path.with_extension(format!("{frame:04}.{}", ext.to_string_lossy())),
+        None => path.with_extension(format!("{frame:04}")),
+    }
+}
@@ -2,100 +2,435 @@ use crate::aabb::{Aabb, Axis};
 use crate::interval::Interval;
 use crate::object::{Hit, Hittable};
 use crate::ray::Ray;
+use crate::rng::SampleRng;
+#[cfg(feature = "f32-bvh")]
+use crate::simd::{AabbF32, RayF32};
+use crate::stats;
+use crate::vec3::Point3;
 use std::sync::Arc;
+use std::time::Instant;
 
 pub type AnyObject = Arc<dyn Hittable>;
 
-// SAH (Surface Area Heuristic)
-fn split_bounds_sah(parent: &Aabb, objects: &mut [AnyObject]) -> usize {
-    let compare: fn(&AnyObject, &AnyObject) -> _ = match parent.longest_axis() {
-        Axis::X => |l, r| l.bbox().x().mid().total_cmp(&r.bbox().x().mid()),
-        Axis::Y => |l, r| l.bbox().y().mid().total_cmp(&r.bbox().y().mid()),
-        Axis::Z => |l, r| l.bbox().z().mid().total_cmp(&r.bbox().z().mid()),
-    };
-    objects.sort_unstable_by(compare);
-
-    // Note: Binned-SAH improves building BVH by 5x faster but it didn't improve entire performance so far
-    let len = objects.len();
-    let mut sum_left = 0.0;
-    let mut sum_right: f64 = objects.iter().map(|h| h.bbox().surface()).sum();
+// Objects with their bounding boxes cached while building the hierarchy
+struct Primitive {
+    object: AnyObject,
+    bbox: Aabb,
+    centroid: Point3,
+}
+
+// Number of bins for binned SAH
+const BINS: usize = 16;
+
+// Subtrees with more objects than this are built in parallel
+const PARALLEL_THRESHOLD: usize = 1024;
+
+// Longest axis of the centroids of the objects and the range of the centroids along it
+fn centroid_axis(prims: &[Primitive]) -> (Axis, Interval) {
+    // Note: `Aabb` is not used for the bounds of centroids since it pads the thin axes
+    let mut centroids = [Interval::default(); 3];
+    for p in prims.iter() {
+        for (i, c) in centroids.iter_mut().enumerate() {
+            *c = Interval::new_covered(*c, Interval::new(p.centroid[i], p.centroid[i]));
+        }
+    }
+    let axis = [Axis::X, Axis::Y, Axis::Z]
+        .into_iter()
+        .max_by(|&l, &r| centroids[l as usize].len().total_cmp(&centroids[r as usize].len()))
+        .unwrap();
+    (axis, centroids[axis as usize])
+}
+
+// Split the objects into two halves at the median of their centroids along the longest axis. This keeps the depth
+// logarithmic however the objects are distributed. Returns the number of objects in the left side and the axis.
+fn split_median(prims: &mut [Primitive]) -> (usize, Axis) {
+    let (axis, _) = centroid_axis(prims);
+    let mid = prims.len() / 2;
+    prims.select_nth_unstable_by(mid, |l, r| {
+        l.centroid[axis as usize].total_cmp(&r.centroid[axis as usize])
+    });
+    (mid, axis)
+}
+
+// Binned SAH (Surface Area Heuristic). Objects are put into the bins along the longest axis of their centroids and
+// the split cost is evaluated only at the boundaries of the bins. This avoids sorting all objects at every level.
+// Returns the number of objects in the left side after partitioning the objects, and the axis of the split.
+fn split_binned_sah(prims: &mut [Primitive]) -> (usize, Axis) {
+    let (axis, range) = centroid_axis(prims);
+    let len = prims.len();
+    if range.len() <= 0.0 {
+        // All centroids are at the same position
+        return (len / 2, axis);
+    }
+
+    let scale = BINS as f64 / range.len();
+    let bin_of = |p: &Primitive| (((p.centroid[axis as usize] - range.min()) * scale) as usize).min(BINS - 1);
+
+    let mut counts = [0usize; BINS];
+    let mut bboxes: [Option<Aabb>; BINS] = Default::default();
+    for p in prims.iter() {
+        let bin = bin_of(p);
+        counts[bin] += 1;
+        bboxes[bin] = Some(match &bboxes[bin] {
+            Some(b) => Aabb::new_contained(b, &p.bbox),
+            None => p.bbox.clone(),
+        });
+    }
+
+    // Sweep from the right to accumulate the areas of the right side of each boundary
+    let mut right_costs = [0.0; BINS];
+    let mut acc: Option<Aabb> = None;
+    let mut count = 0;
+    for i in (1..BINS).rev() {
+        if let Some(b) = &bboxes[i] {
+            acc = Some(acc.map_or_else(|| b.clone(), |a| Aabb::new_contained(&a, b)));
+        }
+        count += counts[i];
+        right_costs[i] = acc.as_ref().map_or(0.0, |a| a.surface() * count as f64);
+    }
+
     let mut min_cost = f64::MAX;
-    let mut min_pos = len / 2;
-    for (i, o) in objects.iter().enumerate().take(len - 2) {
-        let surface = o.bbox().surface();
-        sum_left += surface;
-        sum_right -= surface;
-        let pos = i + 1;
-        let cost = sum_left * pos as f64 + sum_right * (len - pos) as f64;
-        if cost < min_cost {
+    let mut min_bin = BINS / 2;
+    let mut acc: Option<Aabb> = None;
+    let mut count = 0;
+    for i in 0..BINS - 1 {
+        if let Some(b) = &bboxes[i] {
+            acc = Some(acc.map_or_else(|| b.clone(), |a| Aabb::new_contained(&a, b)));
+        }
+        count += counts[i];
+        let left_cost = acc.as_ref().map_or(0.0, |a| a.surface() * count as f64);
+        let cost = left_cost + right_costs[i + 1];
+        if count > 0 && count < len && cost < min_cost {
             min_cost = cost;
-            min_pos = pos;
+            min_bin = i;
         }
     }
-    min_pos
+
+    // Partition the objects into the bins [0, min_bin] and the rest
+    let mut mid = 0;
+    for i in 0..len {
+        if bin_of(&prims[i]) <= min_bin {
+            prims.swap(i, mid);
+            mid += 1;
+        }
+    }
+    if mid == 0 || mid == len {
+        // Every object fell into one side. This happens only when `min_bin` was not updated
+        return (len / 2, axis);
+    }
+    (mid, axis)
+}
+
+// Bounding box of BVH nodes. With `f32-bvh` feature, boxes are stored in f32 and tested with SIMD instructions.
+#[cfg(feature = "f32-bvh")]
+type NodeBox = AabbF32;
+#[cfg(not(feature = "f32-bvh"))]
+type NodeBox = Aabb;
+
+#[cfg(feature = "f32-bvh")]
+fn node_box(bbox: &Aabb) -> NodeBox {
+    AabbF32::from(bbox)
+}
+#[cfg(not(feature = "f32-bvh"))]
+fn node_box(bbox: &Aabb) -> NodeBox {
+    bbox.clone()
+}
+
+// Node of the flattened BVH. Nodes are stored in depth-first order so the first child of an interior node is always
+// the next node in the array.
+struct Node {
+    bbox: NodeBox,
+    // For a leaf node, the index of the first object. For an interior node, the index of the second child node.
+    offset: u32,
+    // Number of objects in the leaf node. 0 means an interior node.
+    count: u32,
+    // Axis which the children were split on. This is used to visit the nearer child first.
+    axis: Axis,
 }
 
-// BVH (Bounding Volume Hierarchy)
+// Maximum depth of the BVH which can be traversed with the fixed-size stack
+const STACK_SIZE: usize = 64;
+
+// Subtrees below this depth are split at the median instead of by SAH. SAH can build a very deep tree for skewed
+// distributions of objects, while median splits add at most 33 levels including leaves since object indices are u32.
+const MEDIAN_DEPTH: usize = STACK_SIZE - 33;
+
+// Default maximum number of objects in a leaf node
+pub const DEFAULT_LEAF_SIZE: usize = 4;
+
+// BVH (Bounding Volume Hierarchy) flattened into a contiguous array of nodes. Each leaf node refers to the range of
+// objects which are sorted while building the hierarchy.
 pub struct Bvh {
-    left: AnyObject,
-    right: AnyObject,
+    nodes: Vec<Node>,
+    objects: Vec<AnyObject>,
     bbox: Aabb,
+    leaf_size: usize,
 }
 
 impl Bvh {
-    pub fn new(objects: &mut [AnyObject]) -> Self {
-        let bbox = objects
+    pub fn new(objects: Vec<AnyObject>, leaf_size: usize) -> Self {
+        Self::with_bboxes(objects, leaf_size, |object| object.bbox())
+    }
+
+    // Hierarchy for rays whose time is in the interval. It is built with the bounding boxes of moving objects during
+    // the interval, which are usually much smaller than the boxes covering their whole motion. Returns `None` when no
+    // object moves so that this hierarchy can be reused as is.
+    pub fn during(&self, time: Interval) -> Option<Self> {
+        let moves = self
+            .objects
+            .iter()
+            .any(|object| object.bbox_during(time) != object.bbox());
+        moves.then(|| Self::with_bboxes(self.objects.clone(), self.leaf_size, |object| object.bbox_during(time)))
+    }
+
+    fn with_bboxes(objects: Vec<AnyObject>, leaf_size: usize, bbox_of: impl Fn(&AnyObject) -> Aabb + Sync) -> Self {
+        use rayon::prelude::*;
+
+        let start = Instant::now();
+        let mut prims: Vec<_> = objects
+            .into_par_iter()
+            .map(|object| {
+                let bbox = bbox_of(&object);
+                let centroid = Point3::new(bbox.x().mid(), bbox.y().mid(), bbox.z().mid());
+                Primitive { object, bbox, centroid }
+            })
+            .collect();
+        if prims.is_empty() {
+            return Self {
+                nodes: vec![],
+                objects: vec![],
+                bbox: Aabb::default(),
+                leaf_size,
+            };
+        }
+        let bbox = prims
             .iter()
-            .map(|o| o.bbox())
+            .map(|p| p.bbox.clone())
             .reduce(|a, b| Aabb::new_contained(&a, &b))
             .unwrap();
+        let (nodes, depth) = Self::build(&mut prims, 0, 0, leaf_size.max(1));
+        debug_assert!(depth <= STACK_SIZE, "BVH is too deep: {depth}");
+        let objects = prims.into_iter().map(|p| p.object).collect();
+        stats::add_build_time(start.elapsed());
+        Self {
+            nodes,
+            objects,
+            bbox,
+            leaf_size,
+        }
+    }
 
-        let (left, right): (AnyObject, AnyObject) = match objects {
-            [] | [_] => panic!("BVH node requires at least two objects"),
-            [l, r] => (l.clone(), r.clone()),
-            _ => {
-                let idx = split_bounds_sah(&bbox, objects);
-                match objects.split_at_mut(idx) {
-                    ([h], objects) | (objects, [h]) => (Arc::new(Self::new(objects)), h.clone()),
-                    (left, right) => (Arc::new(Self::new(left)), Arc::new(Self::new(right))),
-                }
-            }
+    // Build the subtree at the depth for the objects and return its nodes and depth. Indices of the child nodes in
+    // the returned nodes are relative to the root of the subtree.
+    fn build(prims: &mut [Primitive], offset: usize, depth: usize, leaf_size: usize) -> (Vec<Node>, usize) {
+        let bbox = prims
+            .iter()
+            .map(|p| p.bbox.clone())
+            .reduce(|a, b| Aabb::new_contained(&a, &b))
+            .unwrap();
+
+        if prims.len() <= leaf_size {
+            let node = Node {
+                bbox: node_box(&bbox),
+                offset: offset as u32,
+                count: prims.len() as u32,
+                axis: Axis::X, // Unused for leaf nodes
+            };
+            return (vec![node], 1);
+        }
+
+        let (idx, axis) = if depth < MEDIAN_DEPTH {
+            split_binned_sah(prims)
+        } else {
+            split_median(prims)
+        };
+        let (left, right) = prims.split_at_mut(idx);
+        let depth = depth + 1;
+        let ((left, left_depth), (right, right_depth)) = if left.len() + right.len() > PARALLEL_THRESHOLD {
+            rayon::join(
+                || Self::build(left, offset, depth, leaf_size),
+                || Self::build(right, offset + idx, depth, leaf_size),
+            )
+        } else {
+            (
+                Self::build(left, offset, depth, leaf_size),
+                Self::build(right, offset + idx, depth, leaf_size),
+            )
         };
 
-        Self { left, right, bbox }
+        // Concatenate the subtrees after this node and fix their relative indices
+        let mut nodes = Vec::with_capacity(1 + left.len() + right.len());
+        let second = 1 + left.len();
+        nodes.push(Node {
+            bbox: node_box(&bbox),
+            offset: second as u32,
+            count: 0,
+            axis,
+        });
+        let shift = |mut node: Node, by: usize| {
+            if node.count == 0 {
+                node.offset += by as u32;
+            }
+            node
+        };
+        nodes.extend(left.into_iter().map(|n| shift(n, 1)));
+        nodes.extend(right.into_iter().map(|n| shift(n, second)));
+        (nodes, left_depth.max(right_depth) + 1)
     }
 }
 
 impl Hittable for Bvh {
-    fn hit(&self, ray: &Ray, mut time: Interval) -> Option<Hit<'_>> {
-        if !self.bbox.hit(ray, time) {
+    fn hit(&self, ray: &Ray, mut time: Interval, rng: &mut SampleRng) -> Option<Hit<'_>> {
+        if self.nodes.is_empty() {
             return None;
         }
-        let left = self.left.hit(ray, time);
-        if let Some(left) = &left {
-            time.upper_bound(left.time);
+
+        #[cfg(feature = "f32-bvh")]
+        let ray_box = &RayF32::new(ray);
+        #[cfg(not(feature = "f32-bvh"))]
+        let ray_box = ray;
+
+        let dir = ray.direction();
+        let dir_is_neg = [dir.x() < 0.0, dir.y() < 0.0, dir.z() < 0.0];
+
+        // Traverse the hierarchy with an explicit stack instead of recursive calls
+        let mut stack = [0u32; STACK_SIZE];
+        let mut len = 0;
+        let mut idx = 0;
+        let mut closest = None;
+        let (mut node_tests, mut primitive_tests) = (0, 0);
+        loop {
+            let node = &self.nodes[idx];
+            node_tests += 1;
+            if node.bbox.hit(ray_box, time) {
+                if node.count > 0 {
+                    let start = node.offset as usize;
+                    primitive_tests += node.count as u64;
+                    for object in &self.objects[start..start + node.count as usize] {
+                        if let Some(hit) = object.hit(ray, time, rng) {
+                            time.upper_bound(hit.time);
+                            closest = Some(hit);
+                        }
+                    }
+                } else {
+                    // Visit the nearer child first along the ray direction so that farther nodes can be culled by the
+                    // closer hit
+                    let (near, far) = if dir_is_neg[node.axis as usize] {
+                        (node.offset as usize, idx + 1)
+                    } else {
+                        (idx + 1, node.offset as usize)
+                    };
+                    stack[len] = far as u32;
+                    len += 1;
+                    idx = near;
+                    continue;
+                }
+            }
+            if len == 0 {
+                break;
+            }
+            len -= 1;
+            idx = stack[len] as usize;
         }
-        let right = self.right.hit(ray, time);
-        right.or(left)
+        stats::add(|c| {
+            c.node_tests += node_tests;
+            c.primitive_tests += primitive_tests;
+        });
+        closest
     }
 
     fn bbox(&self) -> Aabb {
         self.bbox.clone()
     }
+
+    // The nodes keep the boxes covering the whole motion, but a BVH nested in another one is placed by this box
+    fn bbox_during(&self, time: Interval) -> Aabb {
+        self.objects
+            .iter()
+            .map(|object| object.bbox_during(time))
+            .reduce(|a, b| Aabb::new_contained(&a, &b))
+            .unwrap_or_default()
+    }
 }
 
-#[derive(Default)]
 pub struct BvhBuilder {
     objects: Vec<AnyObject>,
+    leaf_size: usize,
+}
+
+impl Default for BvhBuilder {
+    fn default() -> Self {
+        Self {
+            objects: vec![],
+            leaf_size: DEFAULT_LEAF_SIZE,
+        }
+    }
 }
 
 impl BvhBuilder {
+    // Small groups of objects up to this size are stored as flat lists in leaf nodes
+    pub fn leaf_size(mut self, size: usize) -> Self {
+        self.leaf_size = size;
+        self
+    }
+
+    // Panics when the object is unbounded. Such objects must be added to `World::unbounded` instead.
     pub fn add(&mut self, h: impl Hittable + 'static) {
+        assert!(h.bbox().is_bounded(), "unbounded objects cannot be added to BVH");
         self.objects.push(Arc::new(h));
     }
 
-    pub fn build(mut self) -> Bvh {
-        assert!(self.objects.len() >= 2);
-        Bvh::new(&mut self.objects)
+    pub fn build(self) -> Bvh {
+        Bvh::new(self.objects, self.leaf_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::object::Sphere;
+    use crate::rng::scene_rng;
+    use crate::shapes::Plane;
+    use crate::vec3::{Color, Vec3};
+
+    #[test]
+    fn empty() {
+        let bvh = BvhBuilder::default().build();
+        let ray = Ray::new(Point3::ZERO, Vec3::new(0.0, 0.0, 1.0));
+        assert!(
+            bvh.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut scene_rng(0))
+                .is_none()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "unbounded")]
+    fn unbounded_object() {
+        let mat = Lambertian::solid(Color::new(0.5, 0.5, 0.5));
+        BvhBuilder::default().add(Plane::new(Point3::ZERO, Vec3::new(0.0, 1.0, 0.0), mat));
+    }
+
+    #[test]
+    fn skewed_objects_are_not_too_deep() {
+        // SAH splits off one sphere at a time from spheres at exponentially growing distances
+        let mut builder = BvhBuilder::default().leaf_size(1);
+        let mat = Lambertian::solid(Color::new(0.5, 0.5, 0.5));
+        for i in 0..400 {
+            builder.add(Sphere::stationary(
+                Point3::new(2f64.powi(i), 0.0, 0.0),
+                0.1,
+                mat.clone(),
+            ));
+        }
+        let bvh = builder.build();
+        for i in [0, 1, 10, 40] {
+            let x = 2f64.powi(i);
+            let ray = Ray::new(Point3::new(x, 0.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
+            let hit = bvh
+                .hit(&ray, Interval::new(0.001, f64::INFINITY), &mut scene_rng(0))
+                .unwrap();
+            assert!((hit.pos - Point3::new(x, 0.0, -0.1)).length() < 1e-9);
+        }
     }
 }
@@ -1,58 +1,127 @@
+use crate::adaptive::{Adaptive, PixelStats};
+use crate::animation::{self, Animation, Keyframe};
+use crate::denoise::{self, Aux, AuxBuffers};
+use crate::distributed::{self, Worker};
 use crate::interval::Interval;
-use crate::object::Hittable;
+#[cfg(feature = "preview")]
+use crate::preview::{self, Input, Preview};
+use crate::progress::Progress;
+use crate::progressive::{Checkpoint, Progressive, Settings};
 use crate::ray::Ray;
+use crate::rng::{SampleRng, pixel_rng, sample_rng};
+use crate::spectrum;
+use crate::stats;
+use crate::tonemap::{self, ToneMap};
+#[cfg(feature = "preview")]
+use crate::transform::Transform;
 use crate::vec3::{Color, Point3, Vec3};
-use rand::random_range;
+use crate::world::World;
+use rand::Rng;
 use std::f64::consts::PI;
 use std::fs::File;
-use std::io::{self, BufWriter, Write};
-use std::iter::repeat_with;
-use std::ops::Add;
+use std::io::{self, BufWriter, Read, Write};
 use std::path::Path;
 
 fn degrees_to_radians(degrees: f64) -> f64 {
     degrees * PI / 180.0
 }
 
-fn to_rgb(c: Color) -> (u8, u8, u8) {
-    // Gamma correction. Convert linear space to gamma space (γ=2.0)
-    fn linear_to_gamma(linear_component: f64) -> f64 {
-        if linear_component > 0.0 {
-            linear_component.sqrt()
-        } else {
-            0.0
-        }
-    }
+// Number of primary rays averaged in each pixel of the auxiliary buffers
+const AUX_SAMPLES: u32 = 4;
 
-    let r = linear_to_gamma(c.x());
-    let g = linear_to_gamma(c.y());
-    let b = linear_to_gamma(c.z());
+// Fractional part of the golden ratio. Its multiples are evenly distributed in [0, 1)
+const GOLDEN_RATIO_CONJUGATE: f64 = 0.6180339887498949;
 
-    // Ensure `r`, `g`, and `b` are in range of [0..255]
-    const INTENSITY: Interval = Interval::new(0.0, 0.999);
-    let r = (256.0 * INTENSITY.clamp(r)) as _;
-    let g = (256.0 * INTENSITY.clamp(g)) as _;
-    let b = (256.0 * INTENSITY.clamp(b)) as _;
+// Rectangle region of the image
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+// How rays are cast from the camera. All projections are oriented by `lookfrom`, `lookat` and `vup`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Projection {
+    // Thin lens camera with `vfov` and the defocus blur
+    Perspective,
+    // Parallel rays. The viewport has the same size as the perspective one at `focus_distance`
+    Orthographic,
+    // Equidistant fisheye. The angle from the view direction is proportional to the distance from the image center,
+    // and the circle inscribed in the image height covers the field of view in degrees. Outside the circle is black.
+    Fisheye(f64),
+    // 360-degree panorama. The horizontal axis is the longitude around `vup` and the vertical axis is the latitude.
+    Equirectangular,
+}
 
-    (r, g, b)
+// What each pixel shows. The modes other than `Shaded` are debug outputs (AOVs) of the first hit of primary rays. They
+// skip the path tracing and are written without tone mapping.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    // Path-traced color
+    Shaded,
+    // Shading normal mapped from [-1, 1] to [0, 1]
+    Normal,
+    // Hit distance. Brighter is nearer and the half brightness is at `focus_distance`
+    Depth,
+    // Texture coordinates in red and green
+    Uv,
+    // Number of bounding box tests of BVH nodes from blue (none) to red (`HEAT_MAP_MAX` or more)
+    HeatMap,
+}
+
+// Number of bounding box tests shown in red in the heat map
+const HEAT_MAP_MAX: f64 = 128.0;
+
+fn heat_color(t: f64) -> Color {
+    // Blue -> cyan -> green -> yellow -> red
+    let t = 4.0 * t.clamp(0.0, 1.0);
+    match t {
+        t if t < 1.0 => Color::new(0.0, t, 1.0),
+        t if t < 2.0 => Color::new(0.0, 1.0, 2.0 - t),
+        t if t < 3.0 => Color::new(t - 2.0, 1.0, 0.0),
+        t => Color::new(1.0, 4.0 - t, 0.0),
+    }
 }
 
 pub struct Camera {
-    pub image_width: u32,       // Rendered image width in pixel count
-    pub image_height: u32,      // Rendered image height in pixel count
-    pub samples_per_pixel: u32, // Count of random samples for each pixel
-    pub max_depth: u8,          // Maximum number of ray bounces into scene
-    pub vfov: f64,              // Vertical view angle (field of view)
-    pub lookfrom: Point3,       // Point camera is looking from
-    pub lookat: Point3,         // Point camera is looking at
-    pub vup: Vec3,              // Camera-relative "up" direction
-    pub defocus_angle: f64,     // Variation angle of rays through each pixel
-    pub focus_distance: f64,    // Distance from camera lookfrom point to plane of perfect focus.
-    pixel_samples_scale: f64,   // Color scale factor for a sum of pixel samples
-    center: Point3,             // Camera center
-    pixel00_loc: Point3,        // Location of pixel (0, 0)
-    pixel_delta_u: Vec3,        // Offset to pixel to the right
-    pixel_delta_v: Vec3,        // Offset to pixel below
+    pub image_width: u32,             // Rendered image width in pixel count
+    pub image_height: u32,            // Rendered image height in pixel count
+    pub samples_per_pixel: u32,       // Count of random samples for each pixel
+    pub max_depth: u8,                // Maximum number of ray bounces. Safety cap of Russian roulette
+    pub vfov: f64,                    // Vertical view angle (field of view)
+    pub lookfrom: Point3,             // Point camera is looking from
+    pub lookat: Point3,               // Point camera is looking at
+    pub vup: Vec3,                    // Camera-relative "up" direction
+    pub defocus_angle: f64,           // Variation angle of rays through each pixel
+    pub focus_distance: f64,          // Distance from camera lookfrom point to plane of perfect focus.
+    pub seed: u64,                    // Seed of random numbers. Same seed produces the same image
+    pub tile_size: u32,               // Width and height of the square tile rendered by one task in parallel
+    pub projection: Projection,       // How rays are cast from the camera
+    pub exposure: f64,                // Exposure compensation in stops applied before tone mapping
+    pub tone_map: ToneMap,            // Operator mapping the linear radiance to the displayable range
+    pub mode: Mode,                   // What each pixel shows
+    pub denoise: bool,                // Filter the noise of the output guided by the auxiliary buffers
+    pub write_aux: bool,              // Write the auxiliary buffers (albedo, normal and depth) next to the output
+    pub shutter_open: f64,            // Time when the shutter opens. Motions of objects are defined in [0, 1]
+    pub shutter_close: f64,           // Time when the shutter closes
+    pub lookfrom_end: Option<Point3>, // Point camera is looking from at time 1 when the camera moves
+    pub lookat_end: Option<Point3>,   // Point camera is looking at at time 1 when the camera moves
+    pub keyframes: Vec<Keyframe>,     // Camera path of animations. Only `render_animation` uses this
+    pub spectral: bool,               // Trace one wavelength per path so that dispersive materials split colors
+    pub show_progress: bool,          // Show the progress line on stderr while rendering
+    pixel_samples_scale: f64,         // Color scale factor for a sum of pixel samples
+    view: View,                       // View at time 0
+}
+
+// Camera frame and viewport derived from the camera parameters
+#[derive(Clone, Copy, Default)]
+struct View {
+    center: Point3,      // Camera center
+    pixel00_loc: Point3, // Location of pixel (0, 0)
+    pixel_delta_u: Vec3, // Offset to pixel to the right
+    pixel_delta_v: Vec3, // Offset to pixel below
     cam_u: Vec3,
     cam_v: Vec3,
     cam_w: Vec3,
@@ -66,56 +135,89 @@ impl Camera {
             image_width: 800,
             image_height: 450,
             samples_per_pixel: 100,
-            max_depth: 10,
+            max_depth: 50,
             vfov: 90.0,
             lookfrom: Point3::new(0.0, 0.0, 0.0),
             lookat: Point3::new(0.0, 0.0, -1.0),
             vup: Vec3::new(0.0, 1.0, 0.0),
             defocus_angle: 0.0, // No blur by default
             focus_distance: 10.0,
+            seed: 0,
+            tile_size: 16,
+            projection: Projection::Perspective,
+            exposure: 0.0,
+            tone_map: ToneMap::Clamp,
+            mode: Mode::Shaded,
+            denoise: false,
+            write_aux: false,
+            shutter_open: 0.0,
+            shutter_close: 1.0,
+            lookfrom_end: None,
+            lookat_end: None,
+            keyframes: vec![],
+            spectral: false,
+            show_progress: false,
             pixel_samples_scale: 0.0,
-            center: Point3::ZERO,
-            pixel00_loc: Point3::ZERO,
-            pixel_delta_u: Vec3::ZERO,
-            pixel_delta_v: Vec3::ZERO,
-            cam_u: Vec3::ZERO,
-            cam_v: Vec3::ZERO,
-            cam_w: Vec3::ZERO,
-            defocus_disk_u: Vec3::ZERO,
-            defocus_disk_v: Vec3::ZERO,
+            view: View::default(),
         })
     }
 
     fn initialize(&mut self) {
+        self.pixel_samples_scale = 1.0 / self.samples_per_pixel as f64;
+        self.view = self.view(self.lookfrom, self.lookat);
+    }
+
+    fn view(&self, lookfrom: Point3, lookat: Point3) -> View {
         // Camera
-        self.center = self.lookfrom;
+        let center = lookfrom;
         let theta = degrees_to_radians(self.vfov);
         // Note: Focal length is different from focus distance in general. However in our model they are same. (13.2)
         let viewport_height = 2.0 * (theta / 2.0).tan() * self.focus_distance;
         let viewport_width = viewport_height * (self.image_width as f64 / self.image_height as f64); // We don't use `aspect_ratio` since it's an ideal value
-        self.pixel_samples_scale = 1.0 / self.samples_per_pixel as f64;
 
         // Calculate the u,v,w unit basis vectors for the camera coordinate frame.
-        self.cam_w = (self.lookfrom - self.lookat).unit();
-        self.cam_u = self.vup.cross(&self.cam_w).unit();
-        self.cam_v = self.cam_w.cross(&self.cam_u).unit();
+        let cam_w = (lookfrom - lookat).unit();
+        let cam_u = self.vup.cross(&cam_w).unit();
+        let cam_v = cam_w.cross(&cam_u).unit();
 
         // Vectors across the horizontal and down the vertical viewport edges
-        let viewport_u = viewport_width * self.cam_u;
-        let viewport_v = viewport_height * -self.cam_v;
+        let viewport_u = viewport_width * cam_u;
+        let viewport_v = viewport_height * -cam_v;
 
         // Delta vectors from pixel to pixel
-        self.pixel_delta_u = viewport_u / self.image_width as f64;
-        self.pixel_delta_v = viewport_v / self.image_height as f64;
-        let viewport_upper_left = self.center - self.focus_distance * self.cam_w - viewport_u / 2.0 - viewport_v / 2.0;
+        let pixel_delta_u = viewport_u / self.image_width as f64;
+        let pixel_delta_v = viewport_v / self.image_height as f64;
+        let viewport_upper_left = center - self.focus_distance * cam_w - viewport_u / 2.0 - viewport_v / 2.0;
 
         // Center of the pixel at the top-left corner
-        self.pixel00_loc = viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
+        let pixel00_loc = viewport_upper_left + 0.5 * (pixel_delta_u + pixel_delta_v);
 
         // Calculate the camera defocus disk basis vectors
         let defocus_radius = self.focus_distance * degrees_to_radians(self.defocus_angle / 2.0).tan();
-        self.defocus_disk_u = self.cam_u * defocus_radius;
-        self.defocus_disk_v = self.cam_v * defocus_radius;
+        View {
+            center,
+            pixel00_loc,
+            pixel_delta_u,
+            pixel_delta_v,
+            cam_u,
+            cam_v,
+            cam_w,
+            defocus_disk_u: cam_u * defocus_radius,
+            defocus_disk_v: cam_v * defocus_radius,
+        }
+    }
+
+    // View at the time. A moving camera travels linearly from `lookfrom`/`lookat` at time 0 to
+    // `lookfrom_end`/`lookat_end` at time 1.
+    fn view_at(&self, time: f64) -> View {
+        if self.lookfrom_end.is_none() && self.lookat_end.is_none() {
+            return self.view;
+        }
+        let lerp = |a: Point3, b: Option<Point3>| a + time * (b.unwrap_or(a) - a);
+        self.view(
+            lerp(self.lookfrom, self.lookfrom_end),
+            lerp(self.lookat, self.lookat_end),
+        )
     }
 
     fn prepare_output(&self, path: &Path) -> io::Result<BufWriter<File>> {
@@ -126,67 +228,690 @@ impl Camera {
         Ok(out)
     }
 
-    pub fn render_parallel<H: Hittable>(&mut self, path: impl AsRef<Path>, world: &H) -> io::Result<()> {
+    fn to_rgb(&self, c: Color) -> (u8, u8, u8) {
+        if self.mode == Mode::Shaded {
+            tonemap::to_rgb(c, self.exposure, self.tone_map)
+        } else {
+            let quantize = |x: f64| (255.0 * x.clamp(0.0, 1.0)).round() as u8;
+            (quantize(c.x()), quantize(c.y()), quantize(c.z()))
+        }
+    }
+
+    fn write_image(
+        &self,
+        path: &Path,
+        aux: Option<&AuxBuffers>,
+        pixels: impl IntoIterator<Item = Color>,
+        parallel: bool,
+    ) -> io::Result<()> {
+        let pixels: Vec<_> = pixels.into_iter().collect();
+        let pixels = match aux {
+            Some(aux) if self.denoise && self.mode == Mode::Shaded => denoise::denoise(&pixels, aux, parallel),
+            _ => pixels,
+        };
+        let mut out = self.prepare_output(path)?;
+        for c in pixels {
+            let (r, g, b) = self.to_rgb(c);
+            writeln!(out, "{r} {g} {b}")?;
+        }
+        out.flush()
+    }
+
+    // Render the auxiliary buffers when they are needed for denoising or written to files. Each pixel averages a few
+    // primary rays so that the edges are anti-aliased like the color.
+    fn aux_buffers(&self, path: &Path, world: &World, parallel: bool) -> io::Result<Option<AuxBuffers>> {
+        use rayon::prelude::*;
+
+        if !self.denoise && !self.write_aux {
+            return Ok(None);
+        }
+
+        let aux_pixel = |slot: u32| {
+            let (h, w) = (slot / self.image_width, slot % self.image_width);
+            let mut sum = Aux::default();
+            for sample in 0..AUX_SAMPLES {
+                let aux = self.aux_sample(w, h, sample, world);
+                sum.albedo += aux.albedo;
+                sum.normal += aux.normal;
+                sum.depth += aux.depth;
+            }
+            let scale = 1.0 / AUX_SAMPLES as f64;
+            Aux {
+                albedo: sum.albedo * scale,
+                normal: sum.normal * scale,
+                depth: sum.depth * scale,
+            }
+        };
+        let slots = 0..self.image_width * self.image_height;
+        let pixels = if parallel {
+            slots.into_par_iter().map(aux_pixel).collect()
+        } else {
+            slots.map(aux_pixel).collect()
+        };
+        let aux = AuxBuffers {
+            width: self.image_width,
+            height: self.image_height,
+            pixels,
+        };
+
+        if self.write_aux {
+            self.write_aux_buffers(path, &aux)?;
+        }
+        Ok(Some(aux))
+    }
+
+    // Write "<name>.albedo.ppm", "<name>.normal.ppm" and "<name>.depth.ppm" next to the output
+    fn write_aux_buffers(&self, path: &Path, aux: &AuxBuffers) -> io::Result<()> {
+        let mut out = self.prepare_output(&path.with_extension("albedo.ppm"))?;
+        for p in &aux.pixels {
+            let (r, g, b) = tonemap::to_rgb(p.albedo, 0.0, ToneMap::Clamp);
+            writeln!(out, "{r} {g} {b}")?;
+        }
+        out.flush()?;
+
+        // Map each component from [-1, 1] to [0, 255]
+        let mut out = self.prepare_output(&path.with_extension("normal.ppm"))?;
+        for p in &aux.pixels {
+            let [r, g, b] = [p.normal.x(), p.normal.y(), p.normal.z()].map(|v| (127.5 * (v + 1.0)).round() as u8);
+            writeln!(out, "{r} {g} {b}")?;
+        }
+        out.flush()?;
+
+        // Nearer is brighter. Rays hitting nothing are black
+        let max_depth = aux.pixels.iter().map(|p| p.depth).fold(0.0, f64::max);
+        let mut out = self.prepare_output(&path.with_extension("depth.ppm"))?;
+        for p in &aux.pixels {
+            let v = if p.depth > 0.0 {
+                (255.0 * (1.0 - 0.9 * p.depth / max_depth)).round() as u8
+            } else {
+                0
+            };
+            writeln!(out, "{v} {v} {v}")?;
+        }
+        out.flush()
+    }
+
+    fn tiles(&self) -> Vec<Tile> {
+        let size = self.tile_size.max(1);
+        let mut tiles = vec![];
+        for y in (0..self.image_height).step_by(size as _) {
+            for x in (0..self.image_width).step_by(size as _) {
+                let width = size.min(self.image_width - x);
+                let height = size.min(self.image_height - y);
+                tiles.push(Tile { x, y, width, height });
+            }
+        }
+        tiles
+    }
+
+    pub fn render_parallel(&mut self, path: impl AsRef<Path>, world: &World) -> io::Result<()> {
         use rayon::prelude::*;
 
         self.initialize();
+        let path = path.as_ref();
+        let aux = self.aux_buffers(path, world, true)?;
 
-        let pixels: Vec<_> = (0..self.image_height * self.image_width)
+        // Render the image tile by tile for better cache locality. Neighbor pixels tend to traverse the same BVH nodes.
+        let progress = Progress::new(self.image_width as u64 * self.image_height as u64, self.show_progress);
+        let tiles: Vec<_> = self
+            .tiles()
             .into_par_iter()
-            .map(|slot| {
-                let (h, w) = (slot / self.image_width, slot % self.image_width);
-                let sum = repeat_with(|| self.ray_to(w, h).color(self.max_depth, world))
-                    .take(self.samples_per_pixel as _)
-                    .reduce(Add::add)
-                    .unwrap_or_default();
-                to_rgb(sum * self.pixel_samples_scale)
+            .map(|tile| {
+                let mut colors = Vec::with_capacity((tile.width * tile.height) as _);
+                for h in tile.y..tile.y + tile.height {
+                    for w in tile.x..tile.x + tile.width {
+                        colors.push(self.pixel_color(w, h, world));
+                    }
+                }
+                progress.add(colors.len() as _);
+                (tile, colors)
             })
             .collect();
+        progress.finish();
 
-        let mut out = self.prepare_output(path.as_ref())?;
-        for (r, g, b) in pixels {
-            writeln!(out, "{r} {g} {b}")?;
+        let mut pixels = vec![Color::ZERO; (self.image_width * self.image_height) as _];
+        for (tile, colors) in tiles {
+            self.copy_tile(&mut pixels, &tile, &colors);
         }
 
+        self.write_image(path, aux.as_ref(), pixels, true)
+    }
+
+    fn copy_tile(&self, pixels: &mut [Color], tile: &Tile, colors: &[Color]) {
+        for (row, colors) in colors.chunks(tile.width as _).enumerate() {
+            let start = ((tile.y + row as u32) * self.image_width + tile.x) as usize;
+            pixels[start..start + colors.len()].copy_from_slice(colors);
+        }
+    }
+
+    // Render the tiles on the worker processes and merge their results. `args` are sent to the workers so that they
+    // build the same scene and camera. Since the RNG of each sample is derived from its index, the image is identical
+    // to the one rendered by `render_parallel`.
+    pub fn render_distributed(
+        &mut self,
+        path: impl AsRef<Path>,
+        world: &World,
+        workers: Vec<Worker>,
+        args: &[String],
+    ) -> io::Result<()> {
+        self.initialize();
+        let path = path.as_ref();
+        let aux = self.aux_buffers(path, world, true)?;
+
+        let progress = Progress::new(self.image_width as u64 * self.image_height as u64, self.show_progress);
+        let mut pixels = vec![Color::ZERO; (self.image_width * self.image_height) as _];
+        distributed::dispatch(self.tiles(), workers, args, |tile, colors| {
+            self.copy_tile(&mut pixels, &tile, &colors);
+            progress.add(colors.len() as _);
+        })?;
+        progress.finish();
+
+        self.write_image(path, aux.as_ref(), pixels, true)
+    }
+
+    // Worker side of `render_distributed`. Render the tiles requested from the input and write the results to the
+    // output until the input is closed.
+    pub fn serve_tiles(&mut self, world: &World, mut input: impl Read, mut output: impl Write) -> io::Result<()> {
+        use rayon::prelude::*;
+
+        self.initialize();
+        while let Some(tile) = distributed::read_request(&mut input)? {
+            if tile.x.saturating_add(tile.width) > self.image_width
+                || tile.y.saturating_add(tile.height) > self.image_height
+            {
+                let msg = format!("{tile:?} is out of the image");
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            }
+            let Some(pixels) = tile
+                .width
+                .checked_mul(tile.height)
+                .filter(|&n| n <= distributed::MAX_TILE_PIXELS)
+            else {
+                let msg = format!("{tile:?} has more than {} pixels", distributed::MAX_TILE_PIXELS);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            };
+            let colors: Vec<_> = (0..pixels)
+                .into_par_iter()
+                .map(|i| self.pixel_color(tile.x + i % tile.width, tile.y + i / tile.width, world))
+                .collect();
+            distributed::write_result(&mut output, &tile, &colors)?;
+        }
         Ok(())
     }
 
-    pub fn render<H: Hittable>(&mut self, path: impl AsRef<Path>, world: &H) -> io::Result<()> {
+    pub fn render(&mut self, path: impl AsRef<Path>, world: &World) -> io::Result<()> {
         self.initialize();
+        let path = path.as_ref();
+        let aux = self.aux_buffers(path, world, false)?;
 
-        let mut out = self.prepare_output(path.as_ref())?;
-        for h in 0..self.image_height {
-            for w in 0..self.image_width {
-                let sum = repeat_with(|| self.ray_to(w, h).color(self.max_depth, world))
-                    .take(self.samples_per_pixel as _)
-                    .reduce(Add::add)
-                    .unwrap_or_default();
-                let (r, g, b) = to_rgb(sum * self.pixel_samples_scale);
-                writeln!(out, "{r} {g} {b}")?;
+        let pixels = (0..self.image_height).flat_map(|h| (0..self.image_width).map(move |w| (w, h)));
+        let pixels: Vec<_> = pixels.map(|(w, h)| self.pixel_color(w, h, world)).collect();
+        self.write_image(path, aux.as_ref(), pixels, false)
+    }
+
+    // Render the frames of the animation into the numbered images. The camera follows `keyframes`, or orbits around
+    // `lookat` when there are none. The BVH of the world is rebuilt for each frame only when some objects move.
+    pub fn render_animation(
+        &mut self,
+        path: impl AsRef<Path>,
+        world: &World,
+        animation: &Animation,
+        parallel: bool,
+    ) -> io::Result<()> {
+        let keys = if self.keyframes.is_empty() {
+            animation::turntable(self.lookfrom, self.lookat, self.vup)
+        } else {
+            self.keyframes.clone()
+        };
+        let (open, close) = (self.shutter_open, self.shutter_close);
+        let (lookfrom, lookat) = (self.lookfrom, self.lookat);
+        let (lookfrom_end, lookat_end) = (self.lookfrom_end, self.lookat_end);
+        let frames = animation.frames;
+
+        for frame in 0..frames {
+            let start = (frame as f64 + open) / frames as f64;
+            let end = (frame as f64 + close) / frames as f64;
+            self.shutter_open = start;
+            self.shutter_close = end;
+            self.follow(&keys, start, end);
+
+            let moved = world.during(Interval::new(start, end));
+            let world = moved.as_ref().unwrap_or(world);
+            let path = animation::frame_path(path.as_ref(), frame);
+            if parallel {
+                self.render_parallel(&path, world)?;
+            } else {
+                self.render(&path, world)?;
             }
+            eprintln!("Finished frame {}/{frames}", frame + 1);
         }
+
+        (self.shutter_open, self.shutter_close) = (open, close);
+        (self.lookfrom, self.lookat) = (lookfrom, lookat);
+        (self.lookfrom_end, self.lookat_end) = (lookfrom_end, lookat_end);
         Ok(())
     }
 
-    fn ray_to(&self, w: u32, h: u32) -> Ray {
-        // Construct a camera ray originating from the defocus disk (13.2) and directed at a randomly
-        // sampled point around the pixel location w, h.
+    // Place the camera on the path of the keyframes while the shutter is open from `start` to `end`
+    fn follow(&mut self, keys: &[Keyframe], start: f64, end: f64) {
+        let (from0, at0) = animation::interpolate(keys, start);
+        if end <= start {
+            (self.lookfrom, self.lookat) = (from0, at0);
+            (self.lookfrom_end, self.lookat_end) = (None, None);
+            return;
+        }
+        // `view_at` moves the camera linearly from time 0 to 1. Extend the motion during the shutter to them
+        let (from1, at1) = animation::interpolate(keys, end);
+        let (from_speed, at_speed) = ((from1 - from0) / (end - start), (at1 - at0) / (end - start));
+        self.lookfrom = from0 - start * from_speed;
+        self.lookat = at0 - start * at_speed;
+        self.lookfrom_end = Some(self.lookfrom + from_speed);
+        self.lookat_end = Some(self.lookat + at_speed);
+    }
 
-        // Random pixel location (x, y) in the [-0.5,-0.5]..[+0.5,+0.5] unit square around the center of target pixel
-        let pixel_x = w as f64 + random_range(-0.5..0.5);
-        let pixel_y = h as f64 + random_range(-0.5..0.5);
+    // Show the image in a window while refining it pass by pass. The camera can be moved and tuned with keys, which
+    // restarts the accumulation. The current image is written to the path on request.
+    #[cfg(feature = "preview")]
+    pub fn render_preview(&mut self, path: impl AsRef<Path>, world: &World) -> io::Result<()> {
+        use rayon::prelude::*;
+
+        let mut window = Preview::new(self.image_width, self.image_height)?;
+        eprintln!("{}", preview::HELP);
+        self.initialize();
+        // The accumulation is never saved, so the scene is not recorded
+        let mut acc = Checkpoint::new(self.settings(String::new()));
+
+        loop {
+            let mut restart = false;
+            for input in window.inputs() {
+                match input {
+                    Input::Orbit { yaw, pitch } => self.orbit(yaw, pitch),
+                    Input::Dolly(factor) => self.lookfrom = self.lookat + factor * (self.lookfrom - self.lookat),
+                    Input::Fov(delta) => self.vfov = (self.vfov + delta).clamp(1.0, 179.0),
+                    Input::Defocus(delta) => self.defocus_angle = (self.defocus_angle + delta).max(0.0),
+                    Input::Focus(w, h) => match self.focus_distance_at(w, h, world) {
+                        Some(distance) => self.focus_distance = distance,
+                        None => continue,
+                    },
+                    Input::Save => {
+                        let aux = self.aux_buffers(path.as_ref(), world, true)?;
+                        let scale = 1.0 / acc.passes.max(1) as f64;
+                        self.write_image(path.as_ref(), aux.as_ref(), acc.sums.iter().map(|&c| c * scale), true)?;
+                        eprintln!("Saved {:?} with {} samples per pixel", path.as_ref(), acc.passes);
+                        continue;
+                    }
+                    Input::Quit => return Ok(()),
+                }
+                restart = true;
+            }
+            if restart {
+                // The camera moved by itself is not previewed
+                (self.lookfrom_end, self.lookat_end) = (None, None);
+                self.initialize();
+                stats::reset();
+                acc = Checkpoint::new(self.settings(String::new()));
+            }
+
+            if acc.passes < self.samples_per_pixel {
+                let sample = acc.passes;
+                acc.sums.par_iter_mut().enumerate().for_each(|(slot, sum)| {
+                    let slot = slot as u32;
+                    let (h, w) = (slot / self.image_width, slot % self.image_width);
+                    *sum += self.sample_color(w, h, sample, world);
+                });
+                acc.passes += 1;
+            }
+
+            let title = format!(
+                "raytracing - {}/{} samples, vfov {:.0}, defocus angle {:.1}, focus distance {:.2}",
+                acc.passes, self.samples_per_pixel, self.vfov, self.defocus_angle, self.focus_distance,
+            );
+            let scale = 1.0 / acc.passes.max(1) as f64;
+            window.show(acc.sums.iter().map(|&c| self.to_rgb(c * scale)), &title)?;
+        }
+    }
 
-        let pixel_sample = self.pixel00_loc + pixel_x * self.pixel_delta_u + pixel_y * self.pixel_delta_v;
-        let origin = if self.defocus_angle <= 0.0 {
-            self.center
+    // Rotate `lookfrom` around `lookat`. Yaw rotates around `vup` and pitch rotates toward `vup`
+    #[cfg(feature = "preview")]
+    fn orbit(&mut self, yaw: f64, pitch: f64) {
+        let offset = Transform::rotate(self.vup, -yaw).vector(&(self.lookfrom - self.lookat));
+        let axis = offset.cross(&self.vup);
+        let pitched = Transform::rotate(axis, pitch).vector(&offset);
+        // Stop before looking straight along `vup` where the camera frame is undefined
+        let offset = if pitched.unit().dot(&self.vup.unit()).abs() < 0.99 {
+            pitched
         } else {
-            let p = Vec3::random_in_unit_circle();
-            self.center + p.x() * self.defocus_disk_u + p.y() * self.defocus_disk_v
+            offset
+        };
+        self.lookfrom = self.lookat + offset;
+    }
+
+    // Focus distance which makes the object seen at the center of the pixel sharp. Returns `None` when the pixel sees
+    // nothing. This assumes the perspective projection which is the only one with the defocus blur.
+    pub fn focus_distance_at(&self, w: u32, h: u32, world: &World) -> Option<f64> {
+        let view = &self.view;
+        let target = view.pixel00_loc + w as f64 * view.pixel_delta_u + h as f64 * view.pixel_delta_v;
+        let ray = Ray::new(view.center, target - view.center);
+        let mut rng = sample_rng(self.seed, h * self.image_width + w, 0);
+        let hit = world.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rng)?;
+        Some((hit.pos - view.center).dot(&-view.cam_w))
+    }
+
+    // Settings of the rendering recorded in checkpoints
+    fn settings(&self, scene: String) -> Settings {
+        Settings {
+            width: self.image_width,
+            height: self.image_height,
+            seed: self.seed,
+            max_depth: self.max_depth,
+            scene,
+            options: self.options_hash(),
+        }
+    }
+
+    // Hash of the options which change the samples other than those in `Settings`. The debug format prints floats
+    // exactly, and FNV-1a gives the same hash in every build unlike `DefaultHasher`.
+    fn options_hash(&self) -> u64 {
+        let camera = (
+            self.vfov,
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.defocus_angle,
+            self.focus_distance,
+        );
+        let motion = (
+            self.shutter_open,
+            self.shutter_close,
+            self.lookfrom_end,
+            self.lookat_end,
+        );
+        let options = format!("{:?}", (camera, motion, self.projection, self.mode, self.spectral));
+        options
+            .bytes()
+            .fold(0xcbf29ce484222325, |h, b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+    }
+
+    // Render the image pass by pass. Each pass adds one sample to every pixel. The preview image and the checkpoint
+    // are written after every `progressive.every` passes. Since the RNG of each sample is derived from its index, the
+    // final image is identical to the one rendered by `render_parallel` even if the rendering was resumed.
+    pub fn render_progressive(
+        &mut self,
+        path: impl AsRef<Path>,
+        world: &World,
+        progressive: &Progressive,
+        parallel: bool,
+    ) -> io::Result<()> {
+        use rayon::prelude::*;
+
+        self.initialize();
+        let path = path.as_ref();
+        let aux = self.aux_buffers(path, world, parallel)?;
+
+        let settings = self.settings(progressive.scene.clone());
+        let mut acc = match &progressive.checkpoint {
+            Some(checkpoint) if progressive.resume => {
+                let acc = Checkpoint::load(checkpoint)?;
+                if acc.settings != settings {
+                    let s = &acc.settings;
+                    let mut msg = format!(
+                        "checkpoint {checkpoint:?} was rendered with width={}, height={}, seed={}, depth={}, \
+                         scene={:?}",
+                        s.width, s.height, s.seed, s.max_depth, s.scene,
+                    );
+                    if s.options != settings.options {
+                        msg += " and different camera, projection, mode, shutter or spectral options";
+                    }
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+                }
+                eprintln!("Resuming from pass {}/{}", acc.passes, self.samples_per_pixel);
+                acc
+            }
+            _ => Checkpoint::new(settings),
         };
-        let direction = pixel_sample - origin;
-        // For object animation (motion blur), generate rays at random time in the period [0, 1)
-        let time = random_range(0.0..1.0);
 
-        Ray::new_at(time, origin, direction)
+        while acc.passes < self.samples_per_pixel {
+            let sample = acc.passes;
+            let add_sample = |(slot, sum): (usize, &mut Color)| {
+                let slot = slot as u32;
+                let (h, w) = (slot / self.image_width, slot % self.image_width);
+                *sum += self.sample_color(w, h, sample, world);
+            };
+            if parallel {
+                acc.sums.par_iter_mut().enumerate().for_each(add_sample);
+            } else {
+                acc.sums.iter_mut().enumerate().for_each(add_sample);
+            }
+            acc.passes += 1;
+
+            if acc.passes % progressive.every.max(1) == 0 && acc.passes < self.samples_per_pixel {
+                self.save_progress(path, aux.as_ref(), &acc, progressive, parallel)?;
+                eprintln!("Finished pass {}/{}", acc.passes, self.samples_per_pixel);
+            }
+        }
+
+        self.save_progress(path, aux.as_ref(), &acc, progressive, parallel)
+    }
+
+    fn save_progress(
+        &self,
+        path: &Path,
+        aux: Option<&AuxBuffers>,
+        acc: &Checkpoint,
+        progressive: &Progressive,
+        parallel: bool,
+    ) -> io::Result<()> {
+        let scale = 1.0 / acc.passes.max(1) as f64;
+        self.write_image(path, aux, acc.sums.iter().map(|&c| c * scale), parallel)?;
+        if let Some(checkpoint) = &progressive.checkpoint {
+            acc.save(checkpoint)?;
+        }
+        Ok(())
+    }
+
+    // Render the image with adaptive sampling. Pixels whose estimated noise is still above the threshold are sampled
+    // more, up to `samples_per_pixel`.
+    pub fn render_adaptive(
+        &mut self,
+        path: impl AsRef<Path>,
+        world: &World,
+        adaptive: &Adaptive,
+        parallel: bool,
+    ) -> io::Result<()> {
+        use rayon::prelude::*;
+
+        self.initialize();
+        let path = path.as_ref();
+        let aux = self.aux_buffers(path, world, parallel)?;
+
+        let batch = adaptive.min_samples.clamp(1, self.samples_per_pixel.max(1));
+        let sample_pixel = |slot: u32| {
+            let (h, w) = (slot / self.image_width, slot % self.image_width);
+            let mut stats = PixelStats::default();
+            while stats.count < self.samples_per_pixel {
+                let end = (stats.count + batch).min(self.samples_per_pixel);
+                for sample in stats.count..end {
+                    stats.add(self.sample_color(w, h, sample, world));
+                }
+                if stats.relative_error() <= adaptive.threshold {
+                    break;
+                }
+            }
+            stats
+        };
+        let slots = 0..self.image_height * self.image_width;
+        let stats: Vec<_> = if parallel {
+            slots.into_par_iter().map(sample_pixel).collect()
+        } else {
+            slots.map(sample_pixel).collect()
+        };
+
+        self.write_image(path, aux.as_ref(), stats.iter().map(PixelStats::mean), parallel)?;
+
+        let total: u64 = stats.iter().map(|s| s.count as u64).sum();
+        eprintln!("Average samples per pixel: {:.2}", total as f64 / stats.len() as f64);
+
+        if let Some(map) = &adaptive.sample_map {
+            // Brighter pixel was sampled more. White means `samples_per_pixel`
+            let mut out = self.prepare_output(map)?;
+            for s in &stats {
+                let v = (255.0 * s.count as f64 / self.samples_per_pixel as f64) as u8;
+                writeln!(out, "{v} {v} {v}")?;
+            }
+            out.flush()?;
+        }
+
+        Ok(())
+    }
+
+    fn pixel_color(&self, w: u32, h: u32, world: &World) -> Color {
+        let sum = (0..self.samples_per_pixel)
+            .map(|sample| self.sample_color(w, h, sample, world))
+            .fold(Color::ZERO, |acc, c| acc + c);
+        sum * self.pixel_samples_scale
+    }
+
+    fn sample_color(&self, w: u32, h: u32, sample: u32, world: &World) -> Color {
+        // Each sample has its own RNG so that the result does not depend on how pixels are scheduled
+        let pixel = h * self.image_width + w;
+        let mut rng = sample_rng(self.seed, pixel, sample);
+        let Some(ray) = self.ray_to(w, h, &mut rng) else {
+            return Color::ZERO;
+        };
+        let first_hit = |rng: &mut SampleRng| world.hit(&ray, Interval::new(0.001, f64::INFINITY), rng);
+        match self.mode {
+            Mode::Shaded if self.spectral => {
+                // Wavelengths of the samples in a pixel follow a shifted golden ratio sequence so that they cover the
+                // spectrum evenly and the color noise converges quickly
+                let shift: f64 = pixel_rng(self.seed, pixel).random();
+                let u = (shift + sample as f64 * GOLDEN_RATIO_CONJUGATE).fract();
+                let (lambda, pdf) = spectrum::sample_wavelength(u);
+                let ray = ray.with_wavelength(Some(lambda));
+                ray.color(self.max_depth, world, &mut rng) * spectrum::rgb_weight(lambda, pdf)
+            }
+            Mode::Shaded => ray.color(self.max_depth, world, &mut rng),
+            Mode::Normal => first_hit(&mut rng).map_or(Color::ZERO, |hit| 0.5 * (hit.normal + 1.0)),
+            Mode::Depth => first_hit(&mut rng).map_or(Color::ZERO, |hit| {
+                let depth = (hit.pos - *ray.origin()).length();
+                let v = self.focus_distance / (self.focus_distance + depth);
+                Color::new(v, v, v)
+            }),
+            Mode::Uv => first_hit(&mut rng).map_or(Color::ZERO, |hit| Color::new(hit.u, hit.v, 0.0)),
+            Mode::HeatMap => {
+                let tests = stats::local().node_tests;
+                first_hit(&mut rng);
+                heat_color((stats::local().node_tests - tests) as f64 / HEAT_MAP_MAX)
+            }
+        }
+    }
+
+    // First hit features of one primary ray
+    fn aux_sample(&self, w: u32, h: u32, sample: u32, world: &World) -> Aux {
+        // Use the samples from the end so that the RNGs are not shared with the color samples
+        let pixel = h * self.image_width + w;
+        let mut rng = sample_rng(self.seed, pixel, u32::MAX - sample);
+        let Some(ray) = self.ray_to(w, h, &mut rng) else {
+            return Aux::default();
+        };
+        match world.hit(&ray, Interval::new(0.001, f64::INFINITY), &mut rng) {
+            Some(hit) => Aux {
+                albedo: hit.mat.albedo(&hit),
+                normal: hit.normal,
+                depth: (hit.pos - *ray.origin()).length(),
+            },
+            None => Aux {
+                albedo: world.background.color(&ray),
+                ..Aux::default()
+            },
+        }
+    }
+
+    fn ray_to(&self, w: u32, h: u32, rng: &mut SampleRng) -> Option<Ray> {
+        // Construct a camera ray through a randomly sampled point around the pixel location w, h. Perspective rays
+        // originate from the defocus disk (13.2). Returns `None` outside the image circle of the fisheye projection.
+
+        // Random pixel location (x, y) in the [-0.5,-0.5]..[+0.5,+0.5] unit square around the center of target pixel
+        let pixel_x = w as f64 + rng.random_range(-0.5..0.5);
+        let pixel_y = h as f64 + rng.random_range(-0.5..0.5);
+
+        // Point on the defocus disk (lens)
+        let lens = (self.projection == Projection::Perspective && self.defocus_angle > 0.0)
+            .then(|| Vec3::random_in_unit_circle(rng));
+        // For object animation (motion blur), generate rays at random time while the shutter is open
+        let time = self.shutter_open + rng.random_range(0.0..1.0) * (self.shutter_close - self.shutter_open);
+        let view = self.view_at(time);
+
+        let (origin, direction) = match self.projection {
+            Projection::Perspective => {
+                let pixel_sample = view.pixel00_loc + pixel_x * view.pixel_delta_u + pixel_y * view.pixel_delta_v;
+                let origin = match lens {
+                    None => view.center,
+                    Some(p) => view.center + p.x() * view.defocus_disk_u + p.y() * view.defocus_disk_v,
+                };
+                (origin, pixel_sample - origin)
+            }
+            Projection::Orthographic => {
+                // Move the pixel on the viewport to the plane through the camera center
+                let pixel_sample = view.pixel00_loc + pixel_x * view.pixel_delta_u + pixel_y * view.pixel_delta_v;
+                (pixel_sample + self.focus_distance * view.cam_w, -view.cam_w)
+            }
+            Projection::Fisheye(fov) => {
+                // Offset from the image center in the unit of the half image height
+                let half = self.image_height as f64 / 2.0;
+                let x = (pixel_x + 0.5 - self.image_width as f64 / 2.0) / half;
+                let y = (half - pixel_y - 0.5) / half;
+                let r = (x * x + y * y).sqrt();
+                if r > 1.0 {
+                    return None;
+                }
+                let theta = r * degrees_to_radians(fov) / 2.0;
+                let phi = y.atan2(x);
+                let (sin_theta, cos_theta) = theta.sin_cos();
+                let direction = sin_theta * (phi.cos() * view.cam_u + phi.sin() * view.cam_v) - cos_theta * view.cam_w;
+                (view.center, direction)
+            }
+            Projection::Equirectangular => {
+                // `vup` is the pole so that the horizon stays straight when the camera looks up or down. `cam_u` is
+                // already on the horizon, and the longitude 0 is the view direction projected onto it.
+                let pole = self.vup.unit();
+                let forward = pole.cross(&view.cam_u);
+                let phi = ((pixel_x + 0.5) / self.image_width as f64 - 0.5) * 2.0 * PI;
+                let theta = (0.5 - (pixel_y + 0.5) / self.image_height as f64) * PI;
+                let (sin_phi, cos_phi) = phi.sin_cos();
+                let (sin_theta, cos_theta) = theta.sin_cos();
+                let direction = cos_theta * (sin_phi * view.cam_u + cos_phi * forward) + sin_theta * pole;
+                (view.center, direction)
+            }
+        };
+        Some(Ray::new_at(time, origin, direction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panorama_horizon_is_level() {
+        // The camera looks down, but the middle row of the panorama must still be the horizon
+        let mut cam = Camera::new().unwrap();
+        cam.image_width = 64;
+        cam.image_height = 33;
+        cam.lookat = Point3::new(0.0, -0.5, -1.0);
+        cam.projection = Projection::Equirectangular;
+        cam.initialize();
+
+        let mut rng = sample_rng(0, 0, 0);
+        let bound = (PI / cam.image_height as f64).sin();
+        for w in 0..cam.image_width {
+            let ray = cam.ray_to(w, cam.image_height / 2, &mut rng).unwrap();
+            let y = ray.direction().unit().y();
+            assert!(y.abs() <= bound, "direction of column {w} is {y} above the horizon");
+        }
+
+        // The center of the panorama is in the view direction
+        let ray = cam.ray_to(cam.image_width / 2, cam.image_height / 2, &mut rng).unwrap();
+        let direction = ray.direction().unit();
+        assert!(direction.z() < -0.99, "center of the panorama looks to {direction:?}");
     }
 }
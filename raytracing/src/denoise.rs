@@ -0,0 +1,123 @@
+use crate::vec3::{Color, Vec3};
+use rayon::prelude::*;
+
+// B3-spline kernel of the à-trous wavelet transform
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+// Number of passes. The kernel spreads over 2^ITERATIONS pixels in the last pass
+const ITERATIONS: u32 = 5;
+// Edge-stopping parameters of each feature. Smaller values keep the edges sharper
+const COLOR_SIGMA: f64 = 0.5;
+const NORMAL_SIGMA: f64 = 0.1;
+const ALBEDO_SIGMA: f64 = 0.1;
+const DEPTH_SIGMA: f64 = 0.05; // Relative to the depth
+
+// Features of the first hit of primary rays. They are almost free of noise and tell the filter where the edges are.
+// Rays hitting nothing have the background color as the albedo, zero normal and zero depth.
+#[derive(Clone, Copy, Default)]
+pub struct Aux {
+    pub albedo: Color,
+    pub normal: Vec3,
+    pub depth: f64, // Distance from the camera
+}
+
+impl Aux {
+    // Squared distance of the features scaled by their sigmas. The weight of the neighbor decays exponentially by it
+    fn distance(&self, other: &Self) -> f64 {
+        let normal = (self.normal - other.normal).length_squared() / (NORMAL_SIGMA * NORMAL_SIGMA);
+        let albedo = (self.albedo - other.albedo).length_squared() / (ALBEDO_SIGMA * ALBEDO_SIGMA);
+        let max_depth = self.depth.max(other.depth);
+        let depth = if max_depth > 0.0 {
+            ((self.depth - other.depth) / (DEPTH_SIGMA * max_depth)).powi(2)
+        } else {
+            0.0
+        };
+        normal + albedo + depth
+    }
+}
+
+// Auxiliary buffers of the whole image in row-major order
+pub struct AuxBuffers {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Aux>,
+}
+
+// Compress the high dynamic range so that bright fireflies do not dominate the color distance
+fn compress(c: Color) -> Color {
+    c / (1.0 + c.luminance().max(0.0))
+}
+
+fn filter_pass(src: &[Color], aux: &AuxBuffers, step: i64, color_sigma: f64, parallel: bool) -> Vec<Color> {
+    let (width, height) = (aux.width as i64, aux.height as i64);
+    let filter = |i: usize| {
+        let (x, y) = (i as i64 % width, i as i64 / width);
+        let (center, features) = (compress(src[i]), &aux.pixels[i]);
+        let mut sum = Color::ZERO;
+        let mut weights = 0.0;
+        for (ky, dy) in KERNEL.iter().zip(-2..=2) {
+            let qy = y + dy * step;
+            if !(0..height).contains(&qy) {
+                continue;
+            }
+            for (kx, dx) in KERNEL.iter().zip(-2..=2) {
+                let qx = x + dx * step;
+                if !(0..width).contains(&qx) {
+                    continue;
+                }
+                let q = (qy * width + qx) as usize;
+                let color = (center - compress(src[q])).length_squared() / (color_sigma * color_sigma);
+                let w = kx * ky * (-(color + features.distance(&aux.pixels[q]))).exp();
+                sum += w * src[q];
+                weights += w;
+            }
+        }
+        // The center pixel always has a positive weight
+        sum / weights
+    };
+
+    if parallel {
+        (0..src.len()).into_par_iter().map(filter).collect()
+    } else {
+        (0..src.len()).map(filter).collect()
+    }
+}
+
+// Edge-avoiding à-trous wavelet filter. See "Edge-Avoiding À-Trous Wavelet Transform for fast Global Illumination
+// Filtering" (Dammertz et al. 2010). The colors are divided by the albedo before filtering so that textures are not
+// blurred, and multiplied back after that.
+pub fn denoise(colors: &[Color], aux: &AuxBuffers, parallel: bool) -> Vec<Color> {
+    let demodulate = |c: f64, a: f64| if a > 0.01 { c / a } else { c };
+    let remodulate = |c: f64, a: f64| if a > 0.01 { c * a } else { c };
+
+    let mut colors: Vec<_> = colors
+        .iter()
+        .zip(&aux.pixels)
+        .map(|(c, f)| {
+            let a = f.albedo;
+            Color::new(
+                demodulate(c.x(), a.x()),
+                demodulate(c.y(), a.y()),
+                demodulate(c.z(), a.z()),
+            )
+        })
+        .collect();
+
+    for i in 0..ITERATIONS {
+        // The color differences get smaller as the noise is removed
+        let color_sigma = COLOR_SIGMA / (1 << i) as f64;
+        colors = filter_pass(&colors, aux, 1 << i, color_sigma, parallel);
+    }
+
+    colors
+        .iter()
+        .zip(&aux.pixels)
+        .map(|(c, f)| {
+            let a = f.albedo;
+            Color::new(
+                remodulate(c.x(), a.x()),
+                remodulate(c.y(), a.y()),
+                remodulate(c.z(), a.z()),
+            )
+        })
+        .collect()
+}
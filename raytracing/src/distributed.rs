@@ -0,0 +1,310 @@
+use crate::camera::Tile;
+use crate::vec3::Color;
+use std::collections::VecDeque;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+// Protocol between the coordinator and workers. All integers are little endian.
+//
+// 1. The coordinator sends MAGIC and the command line arguments so that the worker builds the same scene and camera.
+//    Each argument is its byte length (u32) followed by the UTF-8 bytes, after the number of arguments (u32).
+// 2. The coordinator sends a tile (x, y, width and height in u32) and the worker replies with the same tile followed
+//    by the colors of its pixels in row-major order (3 f64 for each pixel). This repeats until the coordinator closes
+//    the connection.
+const MAGIC: &[u8; 8] = b"RTDIST01";
+
+// Limits of the arguments accepted by workers. Anyone can connect to a listening worker, so the sizes sent by the peer
+// are checked before allocating for them.
+const MAX_ARGS: u32 = 1024;
+const MAX_ARG_LEN: u32 = 64 * 1024;
+
+// Limit of the pixels of a tile rendered by workers. The image size also comes from the peer, so the tile is checked
+// against this as well as the image before allocating its colors.
+pub const MAX_TILE_PIXELS: u32 = 1 << 24;
+
+fn read_u32(r: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    r.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+pub fn write_args(w: &mut impl Write, args: &[String]) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&(args.len() as u32).to_le_bytes())?;
+    for arg in args {
+        w.write_all(&(arg.len() as u32).to_le_bytes())?;
+        w.write_all(arg.as_bytes())?;
+    }
+    w.flush()
+}
+
+pub fn read_args(r: &mut impl Read) -> io::Result<Vec<String>> {
+    let mut magic = [0; 8];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("unexpected handshake from the coordinator"));
+    }
+    let count = read_u32(r)?;
+    if count > MAX_ARGS {
+        return Err(invalid(format!("too many arguments: {count}")));
+    }
+    (0..count)
+        .map(|_| {
+            let len = read_u32(r)?;
+            if len > MAX_ARG_LEN {
+                return Err(invalid(format!("argument is too long: {len} bytes")));
+            }
+            let mut buf = vec![0; len as usize];
+            r.read_exact(&mut buf)?;
+            String::from_utf8(buf).map_err(|e| invalid(e.to_string()))
+        })
+        .collect()
+}
+
+fn write_tile(w: &mut impl Write, tile: &Tile) -> io::Result<()> {
+    for v in [tile.x, tile.y, tile.width, tile.height] {
+        w.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+// Read the next tile to render. Returns `None` when the coordinator closed the connection.
+pub fn read_request(r: &mut impl Read) -> io::Result<Option<Tile>> {
+    let mut buf = [0; 4];
+    match r.read_exact(&mut buf) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        res => res?,
+    }
+    Ok(Some(Tile {
+        x: u32::from_le_bytes(buf),
+        y: read_u32(r)?,
+        width: read_u32(r)?,
+        height: read_u32(r)?,
+    }))
+}
+
+pub fn write_result(w: &mut impl Write, tile: &Tile, colors: &[Color]) -> io::Result<()> {
+    write_tile(w, tile)?;
+    for c in colors {
+        for v in [c.x(), c.y(), c.z()] {
+            w.write_all(&v.to_le_bytes())?;
+        }
+    }
+    w.flush()
+}
+
+fn read_result(r: &mut impl Read, tile: &Tile) -> io::Result<Vec<Color>> {
+    let header = Tile {
+        x: read_u32(r)?,
+        y: read_u32(r)?,
+        width: read_u32(r)?,
+        height: read_u32(r)?,
+    };
+    if header != *tile {
+        return Err(invalid(format!("worker returned {header:?} for {tile:?}")));
+    }
+    let mut buf = vec![0; (tile.width * tile.height) as usize * 24];
+    r.read_exact(&mut buf)?;
+    let f = |b: &[u8]| f64::from_le_bytes(b.try_into().unwrap());
+    Ok(buf
+        .chunks(24)
+        .map(|b| Color::new(f(&b[..8]), f(&b[8..16]), f(&b[16..])))
+        .collect())
+}
+
+// Options for distributed rendering
+#[derive(Default)]
+pub struct Distributed {
+    pub workers: usize,       // Number of worker processes started on this machine
+    pub connect: Vec<String>, // Addresses of workers listening on TCP sockets
+}
+
+// Connection to a worker process, either a child process talking through its stdin/stdout or a remote process
+// listening on a TCP socket
+pub struct Worker {
+    name: String,
+    reader: Box<dyn Read + Send>,
+    writer: Box<dyn Write + Send>,
+    child: Option<Child>,
+}
+
+impl Worker {
+    // Start this executable as a worker on this machine
+    pub fn spawn(exe: &Path, index: usize) -> io::Result<Self> {
+        let mut child = Command::new(exe)
+            .arg("--worker")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()?;
+        let reader = BufReader::new(child.stdout.take().unwrap());
+        let writer = BufWriter::new(child.stdin.take().unwrap());
+        Ok(Self {
+            name: format!("#{index} (pid {})", child.id()),
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+            child: Some(child),
+        })
+    }
+
+    // Connect to the worker started with `--listen`
+    pub fn connect(addr: &str) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            name: addr.to_string(),
+            reader: Box::new(BufReader::new(stream.try_clone()?)),
+            writer: Box::new(BufWriter::new(stream)),
+            child: None,
+        })
+    }
+
+    fn render(&mut self, tile: &Tile) -> io::Result<Vec<Color>> {
+        write_tile(&mut self.writer, tile)?;
+        self.writer.flush()?;
+        read_result(&mut self.reader, tile).map_err(|e| match e.kind() {
+            io::ErrorKind::UnexpectedEof => io::Error::new(e.kind(), "connection closed"),
+            _ => e,
+        })
+    }
+
+    // Close the connection so that the worker exits
+    fn finish(self) {
+        drop(self.writer);
+        if let Some(mut child) = self.child {
+            let _ = child.wait();
+        }
+    }
+
+    // Stop the worker which is in an unknown state after an error
+    fn abort(self) {
+        if let Some(mut child) = self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+// Tiles waiting to be rendered. Tiles of failed workers are put back so that other workers render them.
+struct Queue {
+    pending: VecDeque<Tile>,
+    in_flight: usize,
+}
+
+// Render the tiles on the workers and pass each result to `merge` as soon as it arrives. Each worker renders one tile
+// at a time. When a worker fails, its tile is dispatched again to the others. This fails only when all workers failed
+// before finishing the tiles.
+pub fn dispatch(
+    tiles: Vec<Tile>,
+    workers: Vec<Worker>,
+    args: &[String],
+    mut merge: impl FnMut(Tile, Vec<Color>),
+) -> io::Result<()> {
+    let mut remaining = tiles.len();
+    let queue = Mutex::new(Queue {
+        pending: tiles.into(),
+        in_flight: 0,
+    });
+    let cond = Condvar::new();
+
+    // Take the next tile. This waits while other workers are rendering the last tiles since they might fail.
+    let next = || {
+        let mut queue = queue.lock().unwrap();
+        loop {
+            if let Some(tile) = queue.pending.pop_front() {
+                queue.in_flight += 1;
+                return Some(tile);
+            }
+            if queue.in_flight == 0 {
+                return None;
+            }
+            queue = cond.wait(queue).unwrap();
+        }
+    };
+    let settle = |failed: Option<Tile>| {
+        let mut queue = queue.lock().unwrap();
+        queue.in_flight -= 1;
+        queue.pending.extend(failed);
+        cond.notify_all();
+    };
+
+    thread::scope(|s| {
+        let (tx, rx) = mpsc::channel();
+        for mut worker in workers {
+            let tx = tx.clone();
+            s.spawn(move || {
+                if let Err(err) = write_args(&mut worker.writer, args) {
+                    eprintln!("Worker {} failed: {err}", worker.name);
+                    worker.abort();
+                    return;
+                }
+                while let Some(tile) = next() {
+                    match worker.render(&tile) {
+                        Ok(colors) => {
+                            settle(None);
+                            if tx.send((tile, colors)).is_err() {
+                                break;
+                            }
+                        }
+                        Err(err) => {
+                            eprintln!("Worker {} failed: {err}. Its tile is rendered again", worker.name);
+                            settle(Some(tile));
+                            worker.abort();
+                            return;
+                        }
+                    }
+                }
+                worker.finish();
+            });
+        }
+        drop(tx);
+
+        // The channel is closed when all workers finished or failed
+        for (tile, colors) in rx {
+            merge(tile, colors);
+            remaining -= 1;
+        }
+        if remaining > 0 {
+            return Err(io::Error::other(format!(
+                "all workers failed with {remaining} tiles left"
+            )));
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn args_round_trip() {
+        let args = vec!["--scene".to_string(), "fog".to_string(), String::new()];
+        let mut buf = vec![];
+        write_args(&mut buf, &args).unwrap();
+        assert_eq!(read_args(&mut &buf[..]).unwrap(), args);
+    }
+
+    #[test]
+    fn oversized_args() {
+        let mut count = MAGIC.to_vec();
+        count.extend(u32::MAX.to_le_bytes());
+        assert_eq!(
+            read_args(&mut &count[..]).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+
+        let mut len = MAGIC.to_vec();
+        len.extend(1u32.to_le_bytes());
+        len.extend(u32::MAX.to_le_bytes());
+        assert_eq!(read_args(&mut &len[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
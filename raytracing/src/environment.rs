@@ -0,0 +1,287 @@
+use crate::rng::SampleRng;
+use crate::vec3::{Color, Vec3};
+use rand::Rng;
+use std::f64::consts::PI;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+fn rgbe_to_color(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::ZERO;
+    }
+    let f = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new(rgbe[0] as f64 * f, rgbe[1] as f64 * f, rgbe[2] as f64 * f)
+}
+
+// Read one scanline of the new run-length encoding where each channel is encoded separately
+fn read_rle_scanline(r: &mut impl Read, width: usize) -> io::Result<Vec<[u8; 4]>> {
+    let mut line = vec![[0; 4]; width];
+    for ch in 0..4 {
+        let mut x = 0;
+        while x < width {
+            let mut buf = [0; 2];
+            r.read_exact(&mut buf[..1])?;
+            let count = buf[0] as usize;
+            if count > 128 {
+                let count = count - 128;
+                r.read_exact(&mut buf[1..])?;
+                if x + count > width {
+                    return Err(invalid("run exceeds the scanline"));
+                }
+                for px in &mut line[x..x + count] {
+                    px[ch] = buf[1];
+                }
+                x += count;
+            } else {
+                if count == 0 || x + count > width {
+                    return Err(invalid("invalid run in the scanline"));
+                }
+                let mut values = vec![0; count];
+                r.read_exact(&mut values)?;
+                for (px, v) in line[x..x + count].iter_mut().zip(values) {
+                    px[ch] = v;
+                }
+                x += count;
+            }
+        }
+    }
+    Ok(line)
+}
+
+// Largest number of pixels accepted from the header, e.g. 16384x8192
+const MAX_PIXELS: usize = 1 << 27;
+
+// Read a Radiance RGBE image (.hdr). Returns the width, the height and the pixels from the top-left corner.
+fn read_hdr(r: &mut impl BufRead) -> io::Result<(usize, usize, Vec<Color>)> {
+    let mut line = String::new();
+    r.read_line(&mut line)?;
+    if !line.starts_with("#?") {
+        return Err(invalid("not a Radiance HDR file"));
+    }
+    loop {
+        line.clear();
+        if r.read_line(&mut line)? == 0 {
+            return Err(invalid("unexpected end of the header"));
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=")
+            && format != "32-bit_rle_rgbe"
+        {
+            return Err(invalid(format!("unsupported format {format:?}")));
+        }
+    }
+
+    // Only the standard orientation is supported
+    line.clear();
+    r.read_line(&mut line)?;
+    let (height, width): (usize, usize) = match line.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", h, "+X", w] => (h.parse().map_err(invalid_size)?, w.parse().map_err(invalid_size)?),
+        _ => return Err(invalid(format!("unsupported resolution {:?}", line.trim()))),
+    };
+    if width.checked_mul(height).is_none_or(|n| n > MAX_PIXELS) {
+        return Err(invalid(format!("image is too large: {width}x{height}")));
+    }
+
+    // The pixels are not reserved up front so that a corrupt header fails at the end of the file instead of
+    // allocating for the size in the header
+    let mut pixels = vec![];
+    for _ in 0..height {
+        let mut head = [0; 4];
+        r.read_exact(&mut head)?;
+        let scanline = if (8..0x8000).contains(&width) && head[0] == 2 && head[1] == 2 && head[2] < 128 {
+            if ((head[2] as usize) << 8 | head[3] as usize) != width {
+                return Err(invalid("scanline width mismatch"));
+            }
+            read_rle_scanline(r, width)?
+        } else {
+            // Flat scanline without encoding
+            let mut line = vec![head];
+            for _ in 1..width {
+                let mut px = [0; 4];
+                r.read_exact(&mut px)?;
+                line.push(px);
+            }
+            line
+        };
+        pixels.extend(scanline.into_iter().map(rgbe_to_color));
+    }
+
+    Ok((width, height, pixels))
+}
+
+fn invalid_size(err: std::num::ParseIntError) -> io::Error {
+    invalid(format!("invalid image size: {err}"))
+}
+
+// Index of the interval containing the value in the cumulative distribution
+fn find_interval(cdf: &[f64], value: f64) -> usize {
+    cdf.partition_point(|&c| c <= value).clamp(1, cdf.len() - 1) - 1
+}
+
+// Equirectangular environment map. The horizontal axis is the longitude around the y axis and the vertical axis is the
+// latitude from the top. The center of the map is the -z direction. Directions are importance-sampled by the luminance
+// of pixels.
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    marginal: Vec<f64>,    // Cumulative distribution of rows. It has `height + 1` elements
+    conditional: Vec<f64>, // Cumulative distributions of pixels in each row. Each row has `width + 1` elements
+    total: f64,            // Sum of the weights of all pixels
+}
+
+impl EnvironmentMap {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let (width, height, pixels) = read_hdr(&mut BufReader::new(File::open(path)?))?;
+        if width == 0 || height == 0 {
+            return Err(invalid("empty image"));
+        }
+        Ok(Self::new(width, height, pixels))
+    }
+
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height);
+
+        // Pixels near the poles cover smaller solid angles
+        let mut marginal = vec![0.0];
+        let mut conditional = Vec::with_capacity((width + 1) * height);
+        for (y, row) in pixels.chunks(width).enumerate() {
+            let theta = ((y as f64 + 0.5) / height as f64 - 0.5) * PI;
+            let mut sum = 0.0;
+            conditional.push(0.0);
+            for c in row {
+                sum += c.luminance().max(0.0) * theta.cos();
+                conditional.push(sum);
+            }
+            marginal.push(marginal[y] + sum);
+        }
+        let total = marginal[height];
+
+        Self {
+            width,
+            height,
+            pixels,
+            marginal,
+            conditional,
+            total,
+        }
+    }
+
+    // Whether any direction can be sampled. A black map has nothing to sample.
+    pub fn is_sampled(&self) -> bool {
+        self.total > 0.0
+    }
+
+    // Pixel position of the direction and the cosine of its latitude
+    fn pixel(&self, direction: &Vec3) -> (usize, usize, f64) {
+        let d = direction.unit();
+        let u = d.x().atan2(-d.z()) / (2.0 * PI) + 0.5;
+        let theta = d.y().clamp(-1.0, 1.0).asin();
+        let v = 0.5 - theta / PI;
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+        (x, y, theta.cos())
+    }
+
+    pub fn color(&self, direction: &Vec3) -> Color {
+        let (x, y, _) = self.pixel(direction);
+        self.pixels[y * self.width + x]
+    }
+
+    // Probability density (in solid angle) of sampling the direction by `EnvironmentMap::random`
+    pub fn pdf_value(&self, direction: &Vec3) -> f64 {
+        let (x, y, cos_theta) = self.pixel(direction);
+        let row = &self.conditional[y * (self.width + 1)..];
+        let weight = row[x + 1] - row[x];
+        if weight <= 0.0 || cos_theta <= 0.0 {
+            return 0.0;
+        }
+        // Convert the density on the image to the density on the unit sphere
+        let pdf_image = weight / self.total * (self.width * self.height) as f64;
+        pdf_image / (2.0 * PI * PI * cos_theta)
+    }
+
+    pub fn random(&self, rng: &mut SampleRng) -> Vec3 {
+        let y = find_interval(&self.marginal, rng.random_range(0.0..self.total));
+        let row = &self.conditional[y * (self.width + 1)..(y + 1) * (self.width + 1)];
+        let x = find_interval(row, rng.random_range(0.0..row[self.width]));
+
+        let u = (x as f64 + rng.random_range(0.0..1.0)) / self.width as f64;
+        let v = (y as f64 + rng.random_range(0.0..1.0)) / self.height as f64;
+        let phi = (u - 0.5) * 2.0 * PI;
+        let theta = (0.5 - v) * PI;
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        Vec3::new(cos_theta * sin_phi, sin_theta, -cos_theta * cos_phi)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(width: usize, height: usize) -> Vec<u8> {
+        format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {height} +X {width}\n").into_bytes()
+    }
+
+    #[test]
+    fn flat_scanlines() {
+        let mut data = header(2, 2);
+        for px in [[128, 64, 32, 129], [0, 0, 0, 0], [128, 128, 128, 128], [1, 2, 3, 136]] {
+            data.extend(px);
+        }
+        let (width, height, pixels) = read_hdr(&mut &data[..]).unwrap();
+        assert_eq!((width, height), (2, 2));
+        assert_eq!(
+            pixels,
+            [
+                Color::new(1.0, 0.5, 0.25),
+                Color::ZERO,
+                Color::new(0.5, 0.5, 0.5),
+                Color::new(1.0, 2.0, 3.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn rle_scanlines() {
+        let mut data = header(8, 1);
+        data.extend([2, 2, 0, 8]);
+        // Runs of the red and green channels
+        data.extend([128 + 8, 128, 128 + 8, 64]);
+        // Literal values of the blue channel, then runs of the exponent
+        data.extend([4, 32, 32, 32, 32, 128 + 4, 32]);
+        data.extend([128 + 8, 129]);
+        let (_, _, pixels) = read_hdr(&mut &data[..]).unwrap();
+        assert_eq!(pixels, vec![Color::new(1.0, 0.5, 0.25); 8]);
+    }
+
+    #[test]
+    fn corrupt_files() {
+        let read = |data: &[u8]| read_hdr(&mut &data[..]).map(|_| ());
+
+        let mut truncated = header(8, 2);
+        truncated.extend([2, 2, 0, 8, 128 + 8, 1]);
+        assert_eq!(read(&truncated).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut overrun = header(8, 1);
+        overrun.extend([2, 2, 0, 8, 128 + 9, 1]);
+        assert_eq!(read(&overrun).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // The size in the header is rejected before reading the pixels
+        let huge = header(1 << 20, 1 << 20);
+        assert_eq!(read(&huge).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        let overflow = header(usize::MAX, 2);
+        assert_eq!(read(&overflow).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        assert_eq!(read(b"P3\n1 1\n255\n").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
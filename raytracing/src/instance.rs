@@ -0,0 +1,308 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::object::{Face, Hit, Hittable, Light};
+use crate::ray::Ray;
+use crate::rng::SampleRng;
+use crate::transform::{Pose, Transform};
+use crate::vec3::{Point3, Vec3};
+
+// Instances move the ray into the object space instead of moving the object. The hit point and the normal are then
+// moved back to the world space. See 8.1 of "Ray Tracing: The Next Week".
+
+pub struct Translate<H> {
+    object: H,
+    offset: Vec3,
+    bbox: Aabb,
+}
+
+impl<H: Hittable> Translate<H> {
+    pub fn new(object: H, offset: Vec3) -> Self {
+        let bbox = Transform::translate(offset).bbox(&object.bbox());
+        Self { object, offset, bbox }
+    }
+}
+
+impl<H: Hittable> Hittable for Translate<H> {
+    fn hit(&self, ray: &Ray, time: Interval, rng: &mut SampleRng) -> Option<Hit<'_>> {
+        let moved = Ray::new_at(ray.time(), *ray.origin() - self.offset, *ray.direction());
+        let mut hit = self.object.hit(&moved, time, rng)?;
+        hit.pos += self.offset;
+        Some(hit)
+    }
+
+    fn bbox(&self) -> Aabb {
+        self.bbox.clone()
+    }
+
+    fn bbox_during(&self, time: Interval) -> Aabb {
+        Transform::translate(self.offset).bbox(&self.object.bbox_during(time))
+    }
+}
+
+impl<H: Light> Light for Translate<H> {
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object.pdf_value(&(*origin - self.offset), direction)
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut SampleRng) -> Vec3 {
+        self.object.random(&(*origin - self.offset), rng)
+    }
+}
+
+pub struct RotateY<H> {
+    object: H,
+    sin_theta: f64,
+    cos_theta: f64,
+    bbox: Aabb,
+}
+
+impl<H: Hittable> RotateY<H> {
+    pub fn new(object: H, degrees: f64) -> Self {
+        let (sin_theta, cos_theta) = degrees.to_radians().sin_cos();
+        let bbox = Transform::rotate(Vec3::new(0.0, 1.0, 0.0), degrees).bbox(&object.bbox());
+        Self {
+            object,
+            sin_theta,
+            cos_theta,
+            bbox,
+        }
+    }
+
+    fn to_object(&self, v: &Vec3) -> Vec3 {
+        let x = self.cos_theta * v.x() - self.sin_theta * v.z();
+        let z = self.sin_theta * v.x() + self.cos_theta * v.z();
+        Vec3::new(x, v.y(), z)
+    }
+
+    fn to_world(&self, v: &Vec3) -> Vec3 {
+        let x = self.cos_theta * v.x() + self.sin_theta * v.z();
+        let z = -self.sin_theta * v.x() + self.cos_theta * v.z();
+        Vec3::new(x, v.y(), z)
+    }
+}
+
+impl<H: Hittable> Hittable for RotateY<H> {
+    fn hit(&self, ray: &Ray, time: Interval, rng: &mut SampleRng) -> Option<Hit<'_>> {
+        let origin = self.to_object(ray.origin());
+        let direction = self.to_object(ray.direction());
+        let rotated = Ray::new_at(ray.time(), origin, direction);
+        let mut hit = self.object.hit(&rotated, time, rng)?;
+        hit.pos = self.to_world(&hit.pos);
+        hit.normal = self.to_world(&hit.normal);
+        Some(hit)
+    }
+
+    fn bbox(&self) -> Aabb {
+        self.bbox.clone()
+    }
+
+    fn bbox_during(&self, time: Interval) -> Aabb {
+        let degrees = self.sin_theta.atan2(self.cos_theta).to_degrees();
+        Transform::rotate(Vec3::new(0.0, 1.0, 0.0), degrees).bbox(&self.object.bbox_during(time))
+    }
+}
+
+impl<H: Light> Light for RotateY<H> {
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.object
+            .pdf_value(&self.to_object(origin), &self.to_object(direction))
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut SampleRng) -> Vec3 {
+        self.to_world(&self.object.random(&self.to_object(origin), rng))
+    }
+}
+
+// Object placed with an arbitrary affine transformation such as non-uniform scaling
+pub struct Transformed<H> {
+    object: H,
+    transform: Transform,
+    bbox: Aabb,
+}
+
+impl<H: Hittable> Transformed<H> {
+    pub fn new(object: H, transform: Transform) -> Self {
+        let bbox = transform.bbox(&object.bbox());
+        Self {
+            object,
+            transform,
+            bbox,
+        }
+    }
+}
+
+fn hit_transformed<'a>(
+    object: &'a impl Hittable,
+    transform: &Transform,
+    ray: &Ray,
+    time: Interval,
+    rng: &mut SampleRng,
+) -> Option<Hit<'a>> {
+    // Note: The direction is not normalized so the ray parameter `t` is the same in both spaces
+    let inv = transform.inverse();
+    let local = Ray::new_at(ray.time(), inv.point(ray.origin()), inv.vector(ray.direction()));
+    let mut hit = object.hit(&local, time, rng)?;
+    hit.pos = transform.point(&hit.pos);
+    // Orient the normal again in the world space. Transforms mirroring the object (negative determinant) must not
+    // swap the front and back faces.
+    let outward_normal = match hit.face {
+        Face::Front => hit.normal,
+        Face::Back => -hit.normal,
+    };
+    let outward_normal = transform.normal(&outward_normal).unit();
+    hit.face = ray.face(&outward_normal);
+    hit.normal = match hit.face {
+        Face::Front => outward_normal,
+        Face::Back => -outward_normal,
+    };
+    Some(hit)
+}
+
+impl<H: Hittable> Hittable for Transformed<H> {
+    fn hit(&self, ray: &Ray, time: Interval, rng: &mut SampleRng) -> Option<Hit<'_>> {
+        hit_transformed(&self.object, &self.transform, ray, time, rng)
+    }
+
+    fn bbox(&self) -> Aabb {
+        self.bbox.clone()
+    }
+
+    fn bbox_during(&self, time: Interval) -> Aabb {
+        self.transform.bbox(&self.object.bbox_during(time))
+    }
+}
+
+// Number of time steps where the bounding box of `Moving` is evaluated
+const MOTION_STEPS: u32 = 32;
+
+// Object moving through the poses at the keyframes. Poses between the keyframes are interpolated and the object holds
+// the first and last poses outside them. Rays at different times see the object at different places, which blurs any
+// object while the shutter of the camera is open.
+pub struct Moving<H> {
+    object: H,
+    keys: Vec<(f64, Pose)>, // Pairs of the time and the pose sorted by the time
+    bbox: Aabb,
+}
+
+impl<H: Hittable> Moving<H> {
+    // Object moving from the pose `from` at time 0 to the pose `to` at time 1
+    pub fn new(object: H, from: Pose, to: Pose) -> Self {
+        Self::keyframes(object, vec![(0.0, from), (1.0, to)])
+    }
+
+    pub fn keyframes(object: H, mut keys: Vec<(f64, Pose)>) -> Self {
+        assert!(!keys.is_empty());
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let mut moving = Self {
+            object,
+            keys,
+            bbox: Aabb::default(),
+        };
+        moving.bbox = moving.bounds(Interval::new(0.0, 1.0));
+        moving
+    }
+
+    fn pose(&self, time: f64) -> Pose {
+        let idx = self.keys.partition_point(|(t, _)| *t <= time);
+        if idx == 0 {
+            return self.keys[0].1;
+        }
+        if idx == self.keys.len() {
+            return self.keys[idx - 1].1;
+        }
+        let ((t0, from), (t1, to)) = (&self.keys[idx - 1], &self.keys[idx]);
+        from.lerp(to, (time - t0) / (t1 - t0))
+    }
+
+    // Bounding box of the object while the time is in the interval
+    fn bounds(&self, time: Interval) -> Aabb {
+        // Evaluate the poses at the keyframes too so that each step rotates along a single arc
+        let mut times: Vec<_> = (0..=MOTION_STEPS)
+            .map(|i| time.min() + time.len() * i as f64 / MOTION_STEPS as f64)
+            .chain(self.keys.iter().map(|(t, _)| *t).filter(|&t| time.surrounds(t)))
+            .collect();
+        times.sort_by(f64::total_cmp);
+        let poses: Vec<_> = times.iter().map(|&t| self.pose(t)).collect();
+
+        let local = self.object.bbox_during(time);
+        let bbox = poses
+            .iter()
+            .map(|p| p.transform().bbox(&local))
+            .reduce(|a, b| Aabb::new_contained(&a, &b))
+            .unwrap();
+
+        // Corners rotating between the steps move along arcs and bulge out of the boxes by the sagitta at most
+        let (x, y, z) = (local.x(), local.y(), local.z());
+        let radius = [x.min(), x.max()].iter().map(|x| x * x).fold(0.0, f64::max)
+            + [y.min(), y.max()].iter().map(|y| y * y).fold(0.0, f64::max)
+            + [z.min(), z.max()].iter().map(|z| z * z).fold(0.0, f64::max);
+        let scale = poses.iter().map(Pose::max_scale).fold(0.0, f64::max);
+        let step = poses.windows(2).map(|p| p[0].angle_to(&p[1])).fold(0.0, f64::max);
+        let pad = 2.0 * radius.sqrt() * scale * (1.0 - (step / 2.0).cos());
+        Aabb::from_axis(bbox.x().expand(pad), bbox.y().expand(pad), bbox.z().expand(pad))
+    }
+}
+
+impl<H: Hittable> Hittable for Moving<H> {
+    fn hit(&self, ray: &Ray, time: Interval, rng: &mut SampleRng) -> Option<Hit<'_>> {
+        // Skip evaluating the pose for rays missing the whole motion
+        if !self.bbox.hit(ray, time) {
+            return None;
+        }
+        let transform = self.pose(ray.time()).transform();
+        hit_transformed(&self.object, &transform, ray, time, rng)
+    }
+
+    fn bbox(&self) -> Aabb {
+        self.bbox.clone()
+    }
+
+    fn bbox_during(&self, time: Interval) -> Aabb {
+        self.bounds(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::object::Sphere;
+    use crate::rng::scene_rng;
+    use crate::vec3::Color;
+
+    const FOREVER: Interval = Interval::new(0.001, f64::INFINITY);
+
+    #[test]
+    fn nested_motion_bounds() {
+        let sphere = Sphere::stationary(Point3::ZERO, 1.0, Lambertian::solid(Color::new(0.5, 0.5, 0.5)));
+        let moving = Moving::new(
+            sphere,
+            Pose::translate(Vec3::ZERO),
+            Pose::translate(Vec3::new(10.0, 0.0, 0.0)),
+        );
+        let moved = Transform::translate(Vec3::new(0.0, 5.0, 0.0));
+        let nested = Translate::new(Transformed::new(moving, moved), Vec3::new(0.0, 0.0, 3.0));
+
+        let bbox = nested.bbox_during(Interval::new(0.0, 0.1));
+        assert!(bbox.x().max() < 2.5, "{}", bbox.x().max());
+        assert_eq!((bbox.y().mid(), bbox.z().mid()), (5.0, 3.0));
+        assert!(nested.bbox().x().max() >= 11.0);
+    }
+
+    #[test]
+    fn mirrored_keeps_faces() {
+        let sphere = Sphere::stationary(Point3::ZERO, 1.0, Lambertian::solid(Color::new(0.5, 0.5, 0.5)));
+        let mirrored = Transformed::new(sphere, Transform::scale(Vec3::new(-2.0, 1.0, 1.0)));
+
+        let ray = Ray::new(Point3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = mirrored.hit(&ray, FOREVER, &mut scene_rng(0)).unwrap();
+        assert_eq!(hit.face, Face::Front);
+        assert_eq!(hit.pos, Point3::new(2.0, 0.0, 0.0));
+        assert_eq!(hit.normal, Vec3::new(1.0, 0.0, 0.0));
+
+        let ray = Ray::new(Point3::ZERO, Vec3::new(1.0, 0.0, 0.0));
+        let hit = mirrored.hit(&ray, FOREVER, &mut scene_rng(0)).unwrap();
+        assert_eq!(hit.face, Face::Back);
+        assert_eq!(hit.normal, Vec3::new(-1.0, 0.0, 0.0));
+    }
+}
@@ -1,5 +1,5 @@
 // Note: This type is very similar to `std::ops::Range<f64>` but it differs in terms of boundary comparisons.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Interval {
     min: f64,
     max: f64,
@@ -70,3 +70,62 @@ impl Default for Interval {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_empty() {
+        let empty = Interval::default();
+        assert_eq!(empty.len(), 0.0);
+        assert!(!empty.surrounds(0.0));
+    }
+
+    #[test]
+    fn surrounds_excludes_bounds() {
+        let i = Interval::new(1.0, 2.0);
+        assert!(i.surrounds(1.5));
+        assert!(!i.surrounds(1.0));
+        assert!(!i.surrounds(2.0));
+        assert!(!i.surrounds(0.0));
+    }
+
+    #[test]
+    fn clamp() {
+        let i = Interval::new(1.0, 2.0);
+        assert_eq!(i.clamp(0.0), 1.0);
+        assert_eq!(i.clamp(1.5), 1.5);
+        assert_eq!(i.clamp(3.0), 2.0);
+    }
+
+    #[test]
+    fn new_covered() {
+        let i = Interval::new_covered(Interval::new(1.0, 2.0), Interval::new(3.0, 4.0));
+        assert_eq!((i.min(), i.max()), (1.0, 4.0));
+        // Empty interval does not change the other one
+        let i = Interval::new_covered(Interval::default(), Interval::new(3.0, 4.0));
+        assert_eq!((i.min(), i.max()), (3.0, 4.0));
+    }
+
+    #[test]
+    fn expand() {
+        let i = Interval::new(1.0, 2.0).expand(1.0);
+        assert_eq!((i.min(), i.max()), (0.5, 2.5));
+        assert_eq!(i.len(), 2.0);
+        assert_eq!(i.mid(), 1.5);
+    }
+
+    #[test]
+    fn bounds_only_shrink() {
+        let mut i = Interval::new(1.0, 5.0);
+        i.lower_bound(0.0);
+        i.upper_bound(6.0);
+        assert_eq!((i.min(), i.max()), (1.0, 5.0));
+        i.lower_bound(2.0);
+        i.upper_bound(3.0);
+        assert_eq!((i.min(), i.max()), (2.0, 3.0));
+        i.upper_bound(1.0);
+        assert_eq!(i.len(), 0.0);
+    }
+}
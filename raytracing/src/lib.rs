@@ -0,0 +1,31 @@
+pub mod aabb;
+pub mod adaptive;
+pub mod animation;
+pub mod bvh;
+pub mod camera;
+pub mod denoise;
+pub mod distributed;
+pub mod environment;
+pub mod instance;
+pub mod interval;
+pub mod material;
+pub mod medium;
+pub mod microfacet;
+pub mod object;
+pub mod pdf;
+#[cfg(feature = "preview")]
+pub mod preview;
+pub mod progress;
+pub mod progressive;
+pub mod ray;
+pub mod rng;
+pub mod scene;
+pub mod shapes;
+pub mod simd;
+pub mod spectrum;
+pub mod stats;
+pub mod texture;
+pub mod tonemap;
+pub mod transform;
+pub mod vec3;
+pub mod world;
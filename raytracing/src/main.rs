@@ -1,131 +1,23 @@
-mod aabb;
-mod bvh;
-mod camera;
-mod interval;
-mod material;
-mod object;
-mod ray;
-mod texture;
-mod vec3;
-
-use bvh::{Bvh, BvhBuilder};
-use camera::Camera;
-use material::{Dielectric, Lambertian, Metal};
-use object::Sphere;
-use rand::random_range;
-use std::io;
+use raytracing::adaptive::Adaptive;
+use raytracing::animation::Animation;
+use raytracing::camera::{Camera, Mode, Projection};
+use raytracing::distributed::{self, Distributed, Worker};
+use raytracing::environment::EnvironmentMap;
+use raytracing::progressive::Progressive;
+use raytracing::scene::Scene;
+use raytracing::stats::{self, Report};
+use raytracing::tonemap::ToneMap;
+use raytracing::world::{Background, World};
+use std::ffi::OsString;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::net::TcpListener;
 use std::path::PathBuf;
-use texture::CheckerTexture;
-use vec3::{Color, Point3, Vec3};
+use std::sync::Arc;
+use std::time::Instant;
 
-#[derive(Clone, Copy)]
-enum Scene {
-    Demo,
-    Checker,
-}
-
-impl Scene {
-    fn world(self, cam: &mut Camera) -> Bvh {
-        match self {
-            Self::Demo => demo_scene(cam),
-            Self::Checker => checker_scene(cam),
-        }
-    }
-}
-
-fn demo_scene(cam: &mut Camera) -> Bvh {
-    cam.vfov = 20.0;
-    cam.lookfrom = Point3::new(13.0, 2.0, 3.0);
-    cam.lookat = Point3::new(0.0, 0.0, 0.0);
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-    cam.defocus_angle = 0.6;
-    cam.focus_distance = 10.0;
-
-    let mut builder = BvhBuilder::default();
-
-    // Ground
-    builder.add(Sphere::stationary(
-        Point3::new(0.0, -1000.0, 0.0),
-        1000.0,
-        Lambertian::new(CheckerTexture::solid(
-            0.32,
-            Color::new(0.1, 0.1, 0.2),
-            Color::new(0.7, 0.7, 0.7),
-        )),
-    ));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let center = {
-                let x = a as f64 + random_range(0.0..0.9);
-                let z = b as f64 + random_range(0.0..0.9);
-                Point3::new(x, 0.2, z)
-            };
-
-            if (center - Point3::new(4.0, 0.2, 0.0)).length() <= 0.9 {
-                continue;
-            }
-
-            let random = random_range(0.0..1.0);
-            if random < 0.8 {
-                // Diffuse
-                let albedo = Color::random(0.0..1.0) * Color::random(0.0..1.0);
-                let center_end = center + Vec3::new(0.0, rand::random_range(0.0..0.5), 0.0);
-                let material = Lambertian::solid(albedo);
-                let sphere = if random < 0.3 {
-                    Sphere::moving(center, center_end, 0.2, material)
-                } else {
-                    Sphere::stationary(center, 0.2, material)
-                };
-                builder.add(sphere);
-            } else if random < 0.95 {
-                // Metal
-                let albedo = Color::random(0.5..1.0);
-                let fuzz = random_range(0.0..0.5);
-                let sphere = Sphere::stationary(center, 0.2, Metal::new(albedo, fuzz));
-                builder.add(sphere);
-            } else {
-                // Glass
-                let sphere = Sphere::stationary(center, 0.2, Dielectric::new(1.5));
-                builder.add(sphere);
-            }
-        }
-    }
-
-    builder.add(Sphere::stationary(
-        Point3::new(0.0, 1.0, 0.0),
-        1.0,
-        Dielectric::new(1.5),
-    ));
-    builder.add(Sphere::stationary(
-        Point3::new(-4.0, 1.0, 0.0),
-        1.0,
-        Lambertian::solid(Color::new(0.4, 0.2, 0.1)),
-    ));
-    builder.add(Sphere::stationary(
-        Point3::new(4.0, 1.0, 0.0),
-        1.0,
-        Metal::new(Color::new(0.7, 0.6, 0.5), 0.0),
-    ));
-
-    builder.build()
-}
-
-fn checker_scene(cam: &mut Camera) -> Bvh {
-    cam.vfov = 20.0;
-    cam.lookfrom = Point3::new(13.0, 2.0, 3.0);
-    cam.lookat = Point3::new(0.0, 0.0, 0.0);
-    cam.vup = Vec3::new(0.0, 1.0, 0.0);
-    cam.defocus_angle = 0.0;
-
-    let mut builder = BvhBuilder::default();
-
-    let tex = CheckerTexture::solid(0.03, Color::new(0.1, 0.1, 0.2), Color::new(0.7, 0.7, 0.7));
-    let mat = Lambertian::new(tex);
-    builder.add(Sphere::stationary(Point3::new(0.0, -10.0, 0.0), 10.0, mat.clone()));
-    builder.add(Sphere::stationary(Point3::new(0.0, 10.0, 0.0), 10.0, mat));
-
-    builder.build()
+enum StatsFormat {
+    Text,
+    Json,
 }
 
 enum Action {
@@ -133,32 +25,115 @@ enum Action {
         path: PathBuf,
         open: bool,
         parallel: bool,
+        threads: Option<usize>,
         scene: Scene,
+        environment: Option<PathBuf>,
+        stats: Option<StatsFormat>,
+        progressive: Option<Progressive>,
+        adaptive: Option<Adaptive>,
+        animation: Option<Animation>,
+        distributed: Option<Distributed>,
+        preview: bool,
     },
+    // Render tiles for the coordinator through stdin/stdout, or through TCP connections accepted at the address
+    Worker(Option<String>),
     Help(&'static str),
 }
 
-fn parse_args(cam: &mut Camera) -> Result<Action, lexopt::Error> {
+fn parse_args(cam: &mut Camera, args: impl IntoIterator<Item = OsString>) -> Result<Action, lexopt::Error> {
     use lexopt::prelude::*;
 
     let mut path = PathBuf::from("out.ppm");
     let mut open = false;
     let mut parallel = true;
+    let mut threads = None;
     let mut scene = Scene::Demo;
-    let mut parser = lexopt::Parser::from_env();
+    let mut environment: Option<PathBuf> = None;
+    let mut stats = None;
+    let mut progressive: Option<Progressive> = None;
+    let mut adaptive: Option<Adaptive> = None;
+    let mut animation = None;
+    let mut distributed: Option<Distributed> = None;
+    let mut preview = false;
+    let mut fisheye_fov = None;
+    let mut parser = lexopt::Parser::from_args(args);
     while let Some(arg) = parser.next()? {
         match arg {
             Short('w') | Long("width") => cam.image_width = parser.value()?.parse()?,
             Short('h') | Long("height") => cam.image_height = parser.value()?.parse()?,
             Short('s') | Long("samples") => cam.samples_per_pixel = parser.value()?.parse()?,
             Short('d') | Long("depth") => cam.max_depth = parser.value()?.parse()?,
+            Long("seed") => cam.seed = parser.value()?.parse()?,
+            Long("tile-size") => cam.tile_size = parser.value()?.parse()?,
+            Short('j') | Long("threads") => threads = Some(parser.value()?.parse()?),
             Short('o') | Long("open") => open = true,
             Short('1') | Long("serial") => parallel = false,
+            Long("preview") if cfg!(feature = "preview") => preview = true,
+            Long("preview") => return Err("--preview requires building with `--features preview`".into()),
             Long("scene") => match parser.value()?.to_string_lossy().as_ref() {
                 "demo" => scene = Scene::Demo,
                 "checker" => scene = Scene::Checker,
+                "instances" => scene = Scene::Instances,
+                "fog" => scene = Scene::Fog,
+                "lights" => scene = Scene::Lights,
+                "materials" => scene = Scene::Materials,
+                "shapes" => scene = Scene::Shapes,
+                "motion" => scene = Scene::Motion,
+                "dispersion" => scene = Scene::Dispersion,
                 v => return Err(format!("invalid value {v:?} for --scene").into()),
             },
+            Long("shutter-open") => cam.shutter_open = parser.value()?.parse()?,
+            Long("shutter-close") => cam.shutter_close = parser.value()?.parse()?,
+            Long("environment") => environment = Some(parser.value()?.into()),
+            Long("projection") => match parser.value()?.to_string_lossy().as_ref() {
+                "perspective" => cam.projection = Projection::Perspective,
+                "orthographic" => cam.projection = Projection::Orthographic,
+                "fisheye" => cam.projection = Projection::Fisheye(180.0),
+                "equirectangular" => cam.projection = Projection::Equirectangular,
+                v => return Err(format!("invalid value {v:?} for --projection").into()),
+            },
+            Long("fisheye-fov") => fisheye_fov = Some(parser.value()?.parse()?),
+            Long("mode") => match parser.value()?.to_string_lossy().as_ref() {
+                "shaded" => cam.mode = Mode::Shaded,
+                "normal" => cam.mode = Mode::Normal,
+                "depth" => cam.mode = Mode::Depth,
+                "uv" => cam.mode = Mode::Uv,
+                "heatmap" => cam.mode = Mode::HeatMap,
+                v => return Err(format!("invalid value {v:?} for --mode").into()),
+            },
+            Long("spectral") => cam.spectral = true,
+            Long("denoise") => cam.denoise = true,
+            Long("aux") => cam.write_aux = true,
+            Long("exposure") => cam.exposure = parser.value()?.parse()?,
+            Long("tone-map") => match parser.value()?.to_string_lossy().as_ref() {
+                "clamp" => cam.tone_map = ToneMap::Clamp,
+                "reinhard" => cam.tone_map = ToneMap::Reinhard,
+                "aces" => cam.tone_map = ToneMap::Aces,
+                v => return Err(format!("invalid value {v:?} for --tone-map").into()),
+            },
+            Long("stats") => match parser.value()?.to_string_lossy().as_ref() {
+                "text" => stats = Some(StatsFormat::Text),
+                "json" => stats = Some(StatsFormat::Json),
+                v => return Err(format!("invalid value {v:?} for --stats").into()),
+            },
+            Long("progressive") => progressive.get_or_insert_default().every = parser.value()?.parse()?,
+            Long("checkpoint") => progressive.get_or_insert_default().checkpoint = Some(parser.value()?.into()),
+            Long("resume") => progressive.get_or_insert_default().resume = true,
+            Long("adaptive") => adaptive.get_or_insert_default().threshold = parser.value()?.parse()?,
+            Long("min-samples") => adaptive.get_or_insert_default().min_samples = parser.value()?.parse()?,
+            Long("sample-map") => adaptive.get_or_insert_default().sample_map = Some(parser.value()?.into()),
+            Long("frames") => {
+                animation = Some(Animation {
+                    frames: parser.value()?.parse()?,
+                })
+            }
+            Long("workers") => distributed.get_or_insert_default().workers = parser.value()?.parse()?,
+            Long("connect") => distributed
+                .get_or_insert_default()
+                .connect
+                .push(parser.value()?.string()?),
+            Long("worker") => return Ok(Action::Worker(None)),
+            Long("listen") => return Ok(Action::Worker(Some(parser.value()?.string()?))),
             Value(val) => path = val.into(),
             Long("help") => {
                 return Ok(Action::Help(
@@ -171,10 +146,52 @@ Options:
     -w,--width VALUE    Width in pixels (default: 800)
     -h,--height VALUE   Height in pixels (default: 450)
     -s,--samples VALUE  Samples per pixel (default: 100)
-    -d,--depth VALUE    Max depth of ray scattering (default: 10)
+    -d,--depth VALUE    Max depth of ray scattering (default: 50)
+    --seed VALUE        Seed of random numbers. The same seed renders the same image (default: 0)
+    --tile-size VALUE   Width and height of tiles rendered in parallel (default: 16)
+    -j,--threads VALUE  Number of threads for parallel rendering (default: number of CPUs)
     -o,--open           Open the output after finishing the rendering
     -1,--serial         Render output in a single thread
-    --scene VALUE       Scene to render. Available values are "demo", "checker" (default: "demo")
+    --preview           Show the image in a window while refining it. Keys move the camera and Enter writes the
+                        image to PATH. Requires the "preview" feature
+    --mode VALUE        What to render. "shaded" is the path-traced image. "normal", "depth", "uv" and "heatmap"
+                        (BVH node tests of primary rays) are debug outputs skipping path tracing
+                        (default: "shaded")
+    --spectral          Trace a random wavelength per path so that dispersive glass splits light into colors
+    --denoise           Filter the noise of the output with the edge-avoiding wavelet filter
+    --aux               Write the albedo, normal and depth of the first hits to PATH with extensions
+                        ".albedo.ppm", ".normal.ppm" and ".depth.ppm"
+    --exposure VALUE    Exposure compensation in stops. The image is scaled by 2^VALUE (default: 0)
+    --tone-map VALUE    Tone mapping operator. Available values are "clamp", "reinhard", "aces" (default: "clamp")
+    --stats VALUE       Print the statistics of rays, BVH tests and timings after rendering. Available values are
+                        "text", "json"
+    --progressive N     Render progressively and write the preview after every N samples per pixel (default: 10)
+    --checkpoint PATH   Save the checkpoint of progressive rendering to the path
+    --resume            Resume the progressive rendering from the checkpoint specified by --checkpoint
+    --adaptive VALUE    Sample pixels adaptively until the relative noise is below the value. --samples is the
+                        maximum (default: 0.05)
+    --min-samples N     Number of samples traced at once in adaptive sampling (default: 16)
+    --sample-map PATH   Write the number of samples of each pixel in adaptive sampling to the path
+    --frames N          Render N frames of the animation into numbered files such as "out.0000.ppm". The camera
+                        follows the keyframes of the scene or orbits around the scene
+    --workers N         Render tiles on N worker processes started on this machine
+    --connect ADDR      Render tiles on the worker listening at ADDR (e.g. "192.168.0.2:7000"). This option can
+                        be repeated and combined with --workers
+    --listen ADDR       Run as a worker accepting connections from coordinators at ADDR. Other options are sent
+                        from the coordinator
+    --scene VALUE       Scene to render. Available values are "demo", "checker", "instances",
+                        "fog", "lights", "materials", "shapes", "motion", "dispersion" (default: "demo")
+    --environment PATH  Light the scene with the equirectangular HDR image (.hdr) instead of the scene's
+                        background
+    --projection VALUE  Camera projection. Available values are "perspective", "orthographic", "fisheye",
+                        "equirectangular" (default: "perspective")
+    --fisheye-fov VALUE Field of view of the fisheye projection in degrees, up to 360 (default: 180)
+    --shutter-open VALUE
+                        Time when the shutter opens. Objects and the camera move from time 0 to 1. With --frames,
+                        the time is relative to each frame (default: 0)
+    --shutter-close VALUE
+                        Time when the shutter closes. The same value as --shutter-open disables motion blur
+                        (default: 1)
     --help              Show this help
 "#,
                 ));
@@ -183,34 +200,179 @@ Options:
         }
     }
 
+    if let Some(Progressive {
+        resume: true,
+        checkpoint: None,
+        ..
+    }) = &progressive
+    {
+        return Err("--resume requires --checkpoint".into());
+    }
+    if let Some(progressive) = &mut progressive {
+        // The environment map lights the scene, so checkpoints rendered with another one cannot be resumed either
+        progressive.scene = match &environment {
+            Some(path) => format!("{} in {}", scene.name(), path.display()),
+            None => scene.name().into(),
+        };
+    }
+    if let Some(fov) = fisheye_fov {
+        let Projection::Fisheye(projection_fov) = &mut cam.projection else {
+            return Err("--fisheye-fov requires --projection fisheye".into());
+        };
+        if !(0.0 < fov && fov <= 360.0) {
+            return Err("--fisheye-fov must be greater than 0 and at most 360".into());
+        }
+        *projection_fov = fov;
+    }
+    if !(0.0 <= cam.shutter_open && cam.shutter_open <= cam.shutter_close && cam.shutter_close <= 1.0) {
+        return Err("shutter must open and close in this order between time 0 and 1".into());
+    }
+    if progressive.is_some() && adaptive.is_some() {
+        return Err("progressive rendering and adaptive sampling cannot be enabled at once".into());
+    }
+    if let Some(Animation { frames: 0 }) = animation {
+        return Err("--frames must be at least 1".into());
+    }
+    if animation.is_some() && (progressive.is_some() || adaptive.is_some()) {
+        return Err("animation cannot be rendered progressively or adaptively".into());
+    }
+    if distributed.is_some() && (progressive.is_some() || adaptive.is_some() || animation.is_some()) {
+        return Err("distributed rendering cannot be combined with progressive, adaptive or animation".into());
+    }
+    if preview && (progressive.is_some() || adaptive.is_some() || animation.is_some() || distributed.is_some()) {
+        return Err(
+            "--preview cannot be combined with progressive, adaptive, animation or distributed rendering".into(),
+        );
+    }
+    if distributed.is_some() && stats.is_some() {
+        return Err("--stats cannot count the work of distributed workers".into());
+    }
+    if let Some(Distributed {
+        workers: 0, connect, ..
+    }) = &distributed
+        && connect.is_empty()
+    {
+        return Err("distributed rendering requires at least one worker".into());
+    }
+
     Ok(Action::Render {
         path,
         open,
         parallel,
+        threads,
         scene,
+        environment,
+        stats,
+        progressive,
+        adaptive,
+        animation,
+        distributed,
+        preview,
     })
 }
 
+fn build_world(cam: &mut Camera, scene: Scene, environment: Option<PathBuf>) -> io::Result<World> {
+    let mut world = scene.world(cam);
+    if let Some(path) = environment {
+        world.background = Background::Environment(Arc::new(EnvironmentMap::load(&path)?));
+    }
+    Ok(world)
+}
+
+// Serve one coordinator. The scene and the camera are built from the arguments sent by the coordinator
+fn run_worker(input: impl Read, output: impl Write) -> io::Result<()> {
+    let mut input = BufReader::new(input);
+    let args = distributed::read_args(&mut input)?;
+    let mut cam = Camera::new()?;
+    let Action::Render { scene, environment, .. } =
+        parse_args(&mut cam, args.into_iter().map(OsString::from)).map_err(io::Error::other)?
+    else {
+        return Err(io::Error::other("coordinator did not send options to render"));
+    };
+    let world = build_world(&mut cam, scene, environment)?;
+    cam.serve_tiles(&world, input, BufWriter::new(output))
+}
+
 fn main() -> io::Result<()> {
     let mut cam = Camera::new()?;
+    cam.show_progress = true;
 
-    match parse_args(&mut cam).map_err(io::Error::other)? {
+    match parse_args(&mut cam, std::env::args_os().skip(1)).map_err(io::Error::other)? {
         Action::Render {
             path,
             open,
             parallel,
+            threads,
             scene,
+            environment,
+            stats,
+            progressive,
+            adaptive,
+            animation,
+            distributed,
+            preview,
         } => {
-            let world = scene.world(&mut cam);
-            if parallel {
+            if let Some(threads) = threads {
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .build_global()
+                    .map_err(io::Error::other)?;
+            }
+            // The statistics include building the BVH of the scene
+            stats::reset();
+            let world = build_world(&mut cam, scene, environment)?;
+
+            let start = Instant::now();
+            if preview {
+                #[cfg(feature = "preview")]
+                cam.render_preview(&path, &world)?;
+            } else if let Some(distributed) = distributed {
+                let exe = std::env::current_exe()?;
+                let mut workers = (0..distributed.workers)
+                    .map(|i| Worker::spawn(&exe, i))
+                    .collect::<io::Result<Vec<_>>>()?;
+                for addr in &distributed.connect {
+                    workers.push(Worker::connect(addr)?);
+                }
+                let args: Vec<_> = std::env::args_os()
+                    .skip(1)
+                    .map(|a| a.to_string_lossy().into_owned())
+                    .collect();
+                cam.render_distributed(&path, &world, workers, &args)?;
+            } else if let Some(animation) = animation {
+                cam.render_animation(&path, &world, &animation, parallel)?;
+            } else if let Some(progressive) = progressive {
+                cam.render_progressive(&path, &world, &progressive, parallel)?;
+            } else if let Some(adaptive) = adaptive {
+                cam.render_adaptive(&path, &world, &adaptive, parallel)?;
+            } else if parallel {
                 cam.render_parallel(&path, &world)?;
             } else {
                 cam.render(&path, &world)?;
             }
+            match stats {
+                Some(StatsFormat::Text) => println!("{}", Report::new(start.elapsed()).text()),
+                Some(StatsFormat::Json) => println!("{}", Report::new(start.elapsed()).json()),
+                None => {}
+            }
+
             if open {
                 open::that(&path)?;
             }
         }
+        Action::Worker(None) => run_worker(io::stdin(), io::stdout())?,
+        Action::Worker(Some(addr)) => {
+            let listener = TcpListener::bind(&addr)?;
+            eprintln!("Listening on {}", listener.local_addr()?);
+            for stream in listener.incoming() {
+                let stream = stream?;
+                let peer = stream.peer_addr()?;
+                eprintln!("Serving {peer}");
+                if let Err(err) = run_worker(stream.try_clone()?, stream) {
+                    eprintln!("Connection from {peer} failed: {err}");
+                }
+            }
+        }
         Action::Help(help) => println!("{help}"),
     }
     Ok(())
@@ -1,11 +1,48 @@
+use crate::microfacet;
 use crate::object::{Face, Hit};
+use crate::pdf::{Onb, Pdf};
 use crate::ray::Ray;
+use crate::rng::SampleRng;
+use crate::spectrum::{Dispersion, LAMBDA_D};
 use crate::texture::{SolidColor, Texture};
 use crate::vec3::{Color, Vec3};
-use rand::random_range;
+use rand::Rng;
+use std::f64::consts::PI;
+
+// How the incoming ray scatters on the surface
+pub enum Scatter {
+    // Specular reflection or refraction. The scattered ray is determined by the material and cannot be sampled from
+    // other PDFs.
+    Specular(Ray, Color),
+    // Diffuse scattering with the attenuation. The direction can be sampled from any PDF (e.g. towards lights) as long
+    // as the result is weighted by `Material::scattering_pdf`. The PDF is what the material prefers to sample.
+    Diffuse(Color, Pdf),
+    // Scattering whose reflectance varies with the direction in color (e.g. microfacet reflection). The direction is
+    // sampled like `Diffuse` but the sample is weighted by `Material::scattering`.
+    Glossy(Pdf),
+}
 
 pub trait Material: Sync + Send {
-    fn scatter(&self, ray: &Ray, hit: &Hit<'_>) -> Option<(Ray, Color)>;
+    fn scatter(&self, ray: &Ray, hit: &Hit<'_>, rng: &mut SampleRng) -> Option<Scatter>;
+
+    // Probability density of scattering towards the direction. This is only used for `Scatter::Diffuse`.
+    fn scattering_pdf(&self, _ray: &Ray, _hit: &Hit<'_>, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // BRDF multiplied by the cosine term towards the direction. This is only used for `Scatter::Glossy`.
+    fn scattering(&self, _ray: &Ray, _hit: &Hit<'_>, _direction: &Vec3) -> Color {
+        Color::ZERO
+    }
+
+    fn emitted(&self, _ray: &Ray, _hit: &Hit<'_>) -> Color {
+        Color::ZERO
+    }
+
+    // Surface color without lighting. This is used as a guide for denoising.
+    fn albedo(&self, _hit: &Hit<'_>) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
 }
 
 // Lambertian (diffuse) reflectance
@@ -28,20 +65,19 @@ impl<T: Texture> Lambertian<T> {
 }
 
 impl<T: Texture> Material for Lambertian<T> {
-    fn scatter(&self, ray: &Ray, hit: &Hit<'_>) -> Option<(Ray, Color)> {
-        // Diffuse the ray around the normal (the Lambertian reflection)
-        let mut scatter_direction = hit.normal + Vec3::random_unit();
-
-        // Note: If the random unit vector we generate is exactly opposite the normal vector, the two
-        // will sum to zero, which will result in a zero scatter direction vector. This leads to bad
-        // scenarios later on (infinities and NaNs).
-        if scatter_direction.is_near_zero() {
-            scatter_direction = hit.normal;
-        }
-
-        let scattered = Ray::new_at(ray.time(), hit.pos, scatter_direction);
+    fn scatter(&self, _ray: &Ray, hit: &Hit<'_>, _rng: &mut SampleRng) -> Option<Scatter> {
+        // Diffuse the ray around the normal with cosine distribution (the Lambertian reflection)
         let attenuation = self.tex.color(hit.u, hit.v, &hit.pos);
-        Some((scattered, attenuation))
+        Some(Scatter::Diffuse(attenuation, Pdf::cosine(&hit.normal)))
+    }
+
+    fn scattering_pdf(&self, _ray: &Ray, hit: &Hit<'_>, direction: &Vec3) -> f64 {
+        let cos_theta = hit.normal.dot(&direction.unit());
+        (cos_theta / PI).max(0.0)
+    }
+
+    fn albedo(&self, hit: &Hit<'_>) -> Color {
+        self.tex.color(hit.u, hit.v, &hit.pos)
     }
 }
 
@@ -58,8 +94,8 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter(&self, ray: &Ray, hit: &Hit<'_>) -> Option<(Ray, Color)> {
-        let fuzz = self.fuzz * Vec3::random_unit();
+    fn scatter(&self, ray: &Ray, hit: &Hit<'_>, rng: &mut SampleRng) -> Option<Scatter> {
+        let fuzz = self.fuzz * Vec3::random_unit(rng);
         let reflected = ray.direction().reflect(&hit.normal) + fuzz;
         let scattered = Ray::new_at(ray.time(), hit.pos, reflected);
 
@@ -70,17 +106,34 @@ impl Material for Metal {
         }
 
         let attenuation = self.albedo;
-        Some((scattered, attenuation))
+        Some(Scatter::Specular(scattered, attenuation))
+    }
+
+    fn albedo(&self, _hit: &Hit<'_>) -> Color {
+        self.albedo
     }
 }
 
 pub struct Dielectric {
     refraction_index: f64,
+    dispersion: Option<Dispersion>,
 }
 
 impl Dielectric {
     pub fn new(refraction_index: f64) -> Self {
-        Self { refraction_index }
+        Self {
+            refraction_index,
+            dispersion: None,
+        }
+    }
+
+    // Glass whose refractive index depends on the wavelength. Without spectral rendering, the index at the D line is
+    // used for all colors.
+    pub fn dispersive(dispersion: Dispersion) -> Self {
+        Self {
+            refraction_index: dispersion.index(LAMBDA_D),
+            dispersion: Some(dispersion),
+        }
     }
 }
 
@@ -91,19 +144,19 @@ fn reflectance(cosine: f64, refraction_index: f64) -> f64 {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, ray: &Ray, hit: &Hit<'_>) -> Option<(Ray, Color)> {
-        // Note: Outside objects is vacuum
-        let refraction_index = if hit.face == Face::Front {
-            1.0 / self.refraction_index
-        } else {
-            self.refraction_index
+    fn scatter(&self, ray: &Ray, hit: &Hit<'_>, rng: &mut SampleRng) -> Option<Scatter> {
+        let index = match (self.dispersion, ray.wavelength()) {
+            (Some(dispersion), Some(lambda)) => dispersion.index(lambda),
+            _ => self.refraction_index,
         };
+        // Note: Outside objects is vacuum
+        let refraction_index = if hit.face == Face::Front { 1.0 / index } else { index };
         let unit_direction = ray.direction().unit();
         let cos_theta = (-unit_direction).dot(&hit.normal).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
         let direction = if refraction_index * sin_theta > 1.0
-            || reflectance(cos_theta, refraction_index) > random_range(0.0..1.0)
+            || reflectance(cos_theta, refraction_index) > rng.random_range(0.0..1.0)
         {
             // Cannot refract. Yield total internal reflection (11.3)
             unit_direction.reflect(&hit.normal)
@@ -113,6 +166,146 @@ impl Material for Dielectric {
 
         let scattered = Ray::new_at(ray.time(), hit.pos, direction);
         let attenuation = Color::new(1.0, 1.0, 1.0);
-        Some((scattered, attenuation))
+        Some(Scatter::Specular(scattered, attenuation))
+    }
+}
+
+// Physically based material of the metallic-roughness workflow used by common PBR tools (e.g. glTF). The specular
+// reflection follows the GGX microfacet model with Smith's masking-shadowing and Schlick's Fresnel term. Dielectric
+// parts are a diffuse base under the specular coat of 4% reflectance, and metallic parts reflect with the base color.
+// Roughness and metallic are read from the red channel of their textures.
+#[derive(Clone)]
+pub struct Principled<B, R, M> {
+    base_color: B,
+    roughness: R,
+    metallic: M,
+}
+
+impl Principled<SolidColor, SolidColor, SolidColor> {
+    pub fn solid(base_color: Color, roughness: f64, metallic: f64) -> Self {
+        Self::new(
+            SolidColor::new(base_color),
+            SolidColor::new(Color::new(roughness, roughness, roughness)),
+            SolidColor::new(Color::new(metallic, metallic, metallic)),
+        )
+    }
+}
+
+impl<B: Texture, R: Texture, M: Texture> Principled<B, R, M> {
+    pub fn new(base_color: B, roughness: R, metallic: M) -> Self {
+        Self {
+            base_color,
+            roughness,
+            metallic,
+        }
+    }
+
+    // Base color, alpha of the GGX distribution, metallic and reflectance at normal incidence at the hit point
+    fn params(&self, hit: &Hit<'_>) -> (Color, f64, f64, Color) {
+        let base = self.base_color.color(hit.u, hit.v, &hit.pos);
+        let roughness = self.roughness.color(hit.u, hit.v, &hit.pos).x();
+        let metallic = self.metallic.color(hit.u, hit.v, &hit.pos).x().clamp(0.0, 1.0);
+        let f0 = (1.0 - metallic) * Color::new(0.04, 0.04, 0.04) + metallic * base;
+        (base, microfacet::alpha(roughness), metallic, f0)
+    }
+}
+
+impl<B: Texture, R: Texture, M: Texture> Material for Principled<B, R, M> {
+    fn scatter(&self, ray: &Ray, hit: &Hit<'_>, _rng: &mut SampleRng) -> Option<Scatter> {
+        let onb = Onb::new(&hit.normal);
+        let view = onb.local(&-ray.direction().unit());
+        if view.z() <= 0.0 {
+            return None;
+        }
+
+        // Choose the lobe by their rough contributions at normal incidence. Keep sampling the specular lobe sometimes
+        // since its contribution grows at grazing angles.
+        let (base, alpha, metallic, f0) = self.params(hit);
+        let specular = f0.luminance();
+        let diffuse = (1.0 - metallic) * base.luminance();
+        let specular = (specular / (specular + diffuse)).max(0.25);
+
+        Some(Scatter::Glossy(Pdf::Microfacet {
+            onb,
+            view,
+            alpha,
+            specular,
+        }))
+    }
+
+    fn scattering(&self, ray: &Ray, hit: &Hit<'_>, direction: &Vec3) -> Color {
+        let onb = Onb::new(&hit.normal);
+        let v = onb.local(&-ray.direction().unit());
+        let l = onb.local(&direction.unit());
+        if v.z() <= 0.0 || l.z() <= 0.0 {
+            return Color::ZERO;
+        }
+
+        let (base, alpha, metallic, f0) = self.params(hit);
+        let h = (v + l).unit();
+        let fresnel = microfacet::fresnel_schlick(f0, v.dot(&h));
+        let g = microfacet::masking_shadowing(&v, &l, alpha);
+        let specular = fresnel * (microfacet::distribution(&h, alpha) * g / (4.0 * v.z() * l.z()));
+        // The light reflected by the coat does not reach the diffuse base
+        let diffuse = (1.0 - metallic) * (Color::new(1.0, 1.0, 1.0) - fresnel) * base / PI;
+        (specular + diffuse) * l.z()
+    }
+
+    fn albedo(&self, hit: &Hit<'_>) -> Color {
+        self.base_color.color(hit.u, hit.v, &hit.pos)
+    }
+}
+
+// Phase function which scatters the ray in a uniformly random direction. This is used for participating media
+// (volumes) such as smoke and fog.
+pub struct Isotropic<T> {
+    tex: T,
+}
+
+impl Isotropic<SolidColor> {
+    pub fn solid(albedo: Color) -> Self {
+        let tex = SolidColor::new(albedo);
+        Self { tex }
+    }
+}
+
+impl<T: Texture> Material for Isotropic<T> {
+    fn scatter(&self, _ray: &Ray, hit: &Hit<'_>, _rng: &mut SampleRng) -> Option<Scatter> {
+        let attenuation = self.tex.color(hit.u, hit.v, &hit.pos);
+        Some(Scatter::Diffuse(attenuation, Pdf::Sphere))
+    }
+
+    fn scattering_pdf(&self, _ray: &Ray, _hit: &Hit<'_>, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn albedo(&self, hit: &Hit<'_>) -> Color {
+        self.tex.color(hit.u, hit.v, &hit.pos)
+    }
+}
+
+// Material of light sources. It emits the light only from its front face.
+pub struct DiffuseLight<T> {
+    tex: T,
+}
+
+impl DiffuseLight<SolidColor> {
+    pub fn solid(emit: Color) -> Self {
+        let tex = SolidColor::new(emit);
+        Self { tex }
+    }
+}
+
+impl<T: Texture> Material for DiffuseLight<T> {
+    fn scatter(&self, _ray: &Ray, _hit: &Hit<'_>, _rng: &mut SampleRng) -> Option<Scatter> {
+        None
+    }
+
+    fn emitted(&self, _ray: &Ray, hit: &Hit<'_>) -> Color {
+        if hit.face == Face::Front {
+            self.tex.color(hit.u, hit.v, &hit.pos)
+        } else {
+            Color::ZERO
+        }
     }
 }
@@ -0,0 +1,73 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::object::{Face, Hit, Hittable};
+use crate::ray::Ray;
+use crate::rng::SampleRng;
+use crate::vec3::Vec3;
+use rand::Rng;
+
+// Volume with constant density such as smoke and fog. The ray passing through the volume scatters at random distance
+// inside the boundary. The boundary must be convex. See 9 of "Ray Tracing: The Next Week".
+pub struct ConstantMedium<H, M> {
+    boundary: H,
+    neg_inv_density: f64,
+    phase_function: M,
+}
+
+impl<H: Hittable, M: Material> ConstantMedium<H, M> {
+    pub fn new(boundary: H, density: f64, phase_function: M) -> Self {
+        Self {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+impl<H: Hittable, M: Material> Hittable for ConstantMedium<H, M> {
+    fn hit(&self, ray: &Ray, time: Interval, rng: &mut SampleRng) -> Option<Hit<'_>> {
+        // Find where the ray enters and exits the boundary. The ray may start inside the volume
+        let enter = self
+            .boundary
+            .hit(ray, Interval::new(f64::NEG_INFINITY, f64::INFINITY), rng)?
+            .time;
+        let exit = self
+            .boundary
+            .hit(ray, Interval::new(enter + 0.0001, f64::INFINITY), rng)?
+            .time;
+
+        let enter = enter.max(time.min()).max(0.0);
+        let exit = exit.min(time.max());
+        if enter >= exit {
+            return None;
+        }
+
+        let ray_length = ray.direction().length();
+        let distance_inside_boundary = (exit - enter) * ray_length;
+        // Note: `1.0 - x` is in (0, 1] so the logarithm is always finite
+        let hit_distance = self.neg_inv_density * (1.0 - rng.random::<f64>()).ln();
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let time = enter + hit_distance / ray_length;
+        Some(Hit {
+            pos: ray.at(time),
+            normal: Vec3::new(1.0, 0.0, 0.0), // Arbitrary
+            time,
+            face: Face::Front, // Arbitrary
+            mat: &self.phase_function,
+            u: 0.0,
+            v: 0.0,
+        })
+    }
+
+    fn bbox(&self) -> Aabb {
+        self.boundary.bbox()
+    }
+
+    fn bbox_during(&self, time: Interval) -> Aabb {
+        self.boundary.bbox_during(time)
+    }
+}
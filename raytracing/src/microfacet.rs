@@ -0,0 +1,79 @@
+use crate::rng::SampleRng;
+use crate::vec3::{Color, Vec3};
+use rand::Rng;
+use std::f64::consts::PI;
+
+// GGX (Trowbridge-Reitz) microfacet model. All vectors are unit vectors in the local coordinates whose z axis is the
+// normal of the macro surface. See "Microfacet Models for Refraction through Rough Surfaces" (Walter et al. 2007) and
+// "Sampling the GGX Distribution of Visible Normals" (Heitz 2018).
+
+// Perfectly smooth surfaces make the distribution a delta function which cannot be evaluated
+const MIN_ALPHA: f64 = 0.001;
+
+// Map the perceptual roughness used by PBR tools to the alpha parameter of the distribution
+pub fn alpha(roughness: f64) -> f64 {
+    (roughness.clamp(0.0, 1.0).powi(2)).max(MIN_ALPHA)
+}
+
+// Distribution of the micro normals
+pub fn distribution(h: &Vec3, alpha: f64) -> f64 {
+    let a2 = alpha * alpha;
+    let t = h.z() * h.z() * (a2 - 1.0) + 1.0;
+    a2 / (PI * t * t)
+}
+
+// Auxiliary function of Smith's masking-shadowing
+fn lambda(v: &Vec3, alpha: f64) -> f64 {
+    let cos2 = v.z() * v.z();
+    let tan2 = (1.0 - cos2).max(0.0) / cos2;
+    ((1.0 + alpha * alpha * tan2).sqrt() - 1.0) / 2.0
+}
+
+// Fraction of the micro normals visible from the direction
+pub fn masking(v: &Vec3, alpha: f64) -> f64 {
+    1.0 / (1.0 + lambda(v, alpha))
+}
+
+// Height-correlated masking-shadowing of the pair of directions
+pub fn masking_shadowing(v: &Vec3, l: &Vec3, alpha: f64) -> f64 {
+    1.0 / (1.0 + lambda(v, alpha) + lambda(l, alpha))
+}
+
+// Schlick's approximation of the Fresnel reflectance with the reflectance at normal incidence
+pub fn fresnel_schlick(f0: Color, cos_theta: f64) -> Color {
+    f0 + (Color::new(1.0, 1.0, 1.0) - f0) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+// Sample a micro normal visible from the view direction. Reflecting the view by the normal samples the specular lobe
+// much better than sampling the distribution itself at grazing angles.
+pub fn sample_visible_normal(view: &Vec3, alpha: f64, rng: &mut SampleRng) -> Vec3 {
+    // Stretch the view to the hemisphere configuration
+    let vh = Vec3::new(alpha * view.x(), alpha * view.y(), view.z()).unit();
+    let len2 = vh.x() * vh.x() + vh.y() * vh.y();
+    let t1 = if len2 > 0.0 {
+        Vec3::new(-vh.y(), vh.x(), 0.0) / len2.sqrt()
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let t2 = vh.cross(&t1);
+
+    // Sample the projected area of the visible hemisphere
+    let r = rng.random_range(0.0..1.0_f64).sqrt();
+    let phi = 2.0 * PI * rng.random_range(0.0..1.0);
+    let p1 = r * phi.cos();
+    let s = 0.5 * (1.0 + vh.z());
+    let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+    let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+
+    // Unstretch the normal
+    Vec3::new(alpha * nh.x(), alpha * nh.y(), nh.z().max(0.0)).unit()
+}
+
+// Probability density of the direction reflected by the normal sampled by `sample_visible_normal`
+pub fn reflection_pdf(view: &Vec3, l: &Vec3, alpha: f64) -> f64 {
+    if view.z() <= 0.0 || l.z() <= 0.0 {
+        return 0.0;
+    }
+    let h = (*view + *l).unit();
+    masking(view, alpha) * distribution(&h, alpha) / (4.0 * view.z())
+}
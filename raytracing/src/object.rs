@@ -1,9 +1,13 @@
 use crate::aabb::Aabb;
 use crate::interval::Interval;
 use crate::material::Material;
+use crate::pdf::Onb;
 use crate::ray::Ray;
+use crate::rng::SampleRng;
 use crate::vec3::{Point3, Vec3};
+use rand::Rng;
 use std::f64::consts::PI;
+use std::sync::Arc;
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub enum Face {
@@ -22,8 +26,49 @@ pub struct Hit<'a> {
 }
 
 pub trait Hittable: Sync + Send {
-    fn hit(&self, ray: &Ray, time: Interval) -> Option<Hit<'_>>;
+    // `rng` is the generator of the path being traced. Objects such as participating media use it to sample hits
+    fn hit(&self, ray: &Ray, time: Interval, rng: &mut SampleRng) -> Option<Hit<'_>>;
     fn bbox(&self) -> Aabb;
+
+    // Bounding box while the ray time is in the interval. Moving objects can return a smaller box than `Hittable::bbox`
+    // which covers the whole motion.
+    fn bbox_during(&self, _time: Interval) -> Aabb {
+        self.bbox()
+    }
+}
+
+// Object which can be sampled directly as a light source. See `Lights`.
+pub trait Light: Hittable {
+    // Probability density (in solid angle) of sampling the direction from the origin by `Light::random`
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64;
+
+    // Random direction from the origin towards this object
+    fn random(&self, origin: &Point3, rng: &mut SampleRng) -> Vec3;
+}
+
+// Allow sharing one object among multiple instances
+impl<H: Hittable + ?Sized> Hittable for Arc<H> {
+    fn hit(&self, ray: &Ray, time: Interval, rng: &mut SampleRng) -> Option<Hit<'_>> {
+        self.as_ref().hit(ray, time, rng)
+    }
+
+    fn bbox(&self) -> Aabb {
+        self.as_ref().bbox()
+    }
+
+    fn bbox_during(&self, time: Interval) -> Aabb {
+        self.as_ref().bbox_during(time)
+    }
+}
+
+impl<L: Light + ?Sized> Light for Arc<L> {
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        self.as_ref().pdf_value(origin, direction)
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut SampleRng) -> Vec3 {
+        self.as_ref().random(origin, rng)
+    }
 }
 
 pub struct Sphere<M> {
@@ -63,8 +108,9 @@ impl<M> Sphere<M> {
     }
 }
 
-impl<M: Material> Hittable for Sphere<M> {
-    fn hit(&self, ray: &Ray, time: Interval) -> Option<Hit<'_>> {
+impl<M> Sphere<M> {
+    // Ray parameter of the nearest intersection in the interval and the center of the sphere at the time of the ray
+    fn intersect(&self, ray: &Ray, time: Interval) -> Option<(f64, Point3)> {
         let center = self.center.at(ray.time());
         let oc = center - *ray.origin(); // C - Q
         let a = ray.direction().length_squared();
@@ -77,6 +123,13 @@ impl<M: Material> Hittable for Sphere<M> {
 
         let d = discriminant.sqrt();
         let time = [(h - d) / a, (h + d) / a].into_iter().find(|&t| time.surrounds(t))?;
+        Some((time, center))
+    }
+}
+
+impl<M: Material> Hittable for Sphere<M> {
+    fn hit(&self, ray: &Ray, time: Interval, _rng: &mut SampleRng) -> Option<Hit<'_>> {
+        let (time, center) = self.intersect(ray, time)?;
         let pos = ray.at(time);
         let outward_normal = (pos - center) / self.radius;
         let face = ray.face(&outward_normal);
@@ -107,4 +160,123 @@ impl<M: Material> Hittable for Sphere<M> {
     fn bbox(&self) -> Aabb {
         self.bbox.clone()
     }
+
+    fn bbox_during(&self, time: Interval) -> Aabb {
+        let radvec = Vec3::new(self.radius, self.radius, self.radius);
+        let (center0, center1) = (self.center.at(time.min()), self.center.at(time.max()));
+        let bbox0 = Aabb::from_extrema(center0 - radvec, center0 + radvec);
+        let bbox1 = Aabb::from_extrema(center1 - radvec, center1 + radvec);
+        Aabb::new_contained(&bbox0, &bbox1)
+    }
+}
+
+impl<M: Material> Light for Sphere<M> {
+    // Sample the cone which covers the sphere seen from the origin. See 12.3 of "Ray Tracing: The Rest of Your Life".
+    // Note: Moving spheres are sampled at time 0
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction);
+        if self.intersect(&ray, Interval::new(0.001, f64::INFINITY)).is_none() {
+            return 0.0;
+        }
+        let distance_squared = (self.center.at(0.0) - *origin).length_squared();
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).max(0.0).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut SampleRng) -> Vec3 {
+        let direction = self.center.at(0.0) - *origin;
+        let distance_squared = direction.length_squared();
+        let r1: f64 = rng.random_range(0.0..1.0);
+        let r2: f64 = rng.random_range(0.0..1.0);
+        let z = 1.0 + r2 * ((1.0 - self.radius * self.radius / distance_squared).max(0.0).sqrt() - 1.0);
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+        Onb::new(&direction).transform(&Vec3::new(x, y, z))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::rng::scene_rng;
+    use crate::texture::SolidColor;
+    use crate::vec3::Color;
+
+    const FOREVER: Interval = Interval::new(0.001, f64::INFINITY);
+
+    fn unit_sphere() -> Sphere<Lambertian<SolidColor>> {
+        Sphere::stationary(Point3::ZERO, 1.0, Lambertian::solid(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn hit_front() {
+        let sphere = unit_sphere();
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let hit = sphere.hit(&ray, FOREVER, &mut scene_rng(0)).unwrap();
+        assert_eq!(hit.time, 4.0);
+        assert_eq!(hit.pos, Point3::new(0.0, 0.0, 1.0));
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(hit.face, Face::Front);
+    }
+
+    #[test]
+    fn hit_back_from_inside() {
+        let sphere = unit_sphere();
+        let ray = Ray::new(Point3::ZERO, Vec3::new(0.0, 2.0, 0.0));
+        let hit = sphere.hit(&ray, FOREVER, &mut scene_rng(0)).unwrap();
+        // `time` is the ray parameter so the non-unit direction halves it
+        assert_eq!(hit.time, 0.5);
+        assert_eq!(hit.pos, Point3::new(0.0, 1.0, 0.0));
+        // The normal faces against the ray
+        assert_eq!(hit.normal, Vec3::new(0.0, -1.0, 0.0));
+        assert_eq!(hit.face, Face::Back);
+    }
+
+    #[test]
+    fn hit_misses() {
+        let sphere = unit_sphere();
+        let beside = Ray::new(Point3::new(0.0, 1.5, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(sphere.hit(&beside, FOREVER, &mut scene_rng(0)).is_none());
+        let away = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0));
+        assert!(sphere.hit(&away, FOREVER, &mut scene_rng(0)).is_none());
+    }
+
+    #[test]
+    fn hit_respects_time_interval() {
+        let sphere = unit_sphere();
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        // The front is out of the interval so the back is hit
+        let hit = sphere
+            .hit(&ray, Interval::new(4.5, f64::INFINITY), &mut scene_rng(0))
+            .unwrap();
+        assert_eq!(hit.time, 6.0);
+        assert_eq!(hit.face, Face::Back);
+        assert!(sphere.hit(&ray, Interval::new(0.001, 3.0), &mut scene_rng(0)).is_none());
+    }
+
+    #[test]
+    fn hit_uv() {
+        let sphere = unit_sphere();
+        // u goes around the y axis from -x and v goes from the bottom to the top
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hit = sphere.hit(&ray, FOREVER, &mut scene_rng(0)).unwrap();
+        assert!((hit.u - 0.0).abs() < 1e-9 || (hit.u - 1.0).abs() < 1e-9);
+        assert!((hit.v - 0.5).abs() < 1e-9);
+        let ray = Ray::new(Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = sphere.hit(&ray, FOREVER, &mut scene_rng(0)).unwrap();
+        assert!((hit.v - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn hit_moving() {
+        let mat = Lambertian::solid(Color::new(0.5, 0.5, 0.5));
+        let sphere = Sphere::moving(Point3::ZERO, Point3::new(0.0, 0.0, -2.0), 1.0, mat);
+        let ray = |time| Ray::new_at(time, Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(sphere.hit(&ray(0.0), FOREVER, &mut scene_rng(0)).unwrap().time, 4.0);
+        assert_eq!(sphere.hit(&ray(1.0), FOREVER, &mut scene_rng(0)).unwrap().time, 6.0);
+        assert_eq!(sphere.hit(&ray(0.5), FOREVER, &mut scene_rng(0)).unwrap().time, 5.0);
+    }
 }
@@ -0,0 +1,111 @@
+use crate::microfacet;
+use crate::rng::SampleRng;
+use crate::vec3::Vec3;
+use rand::Rng;
+use std::f64::consts::PI;
+
+// Orthonormal basis whose `w` axis is the given vector
+pub struct Onb {
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
+}
+
+impl Onb {
+    pub fn new(n: &Vec3) -> Self {
+        let w = n.unit();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit();
+        let u = w.cross(&v);
+        Self { u, v, w }
+    }
+
+    pub fn w(&self) -> &Vec3 {
+        &self.w
+    }
+
+    // Transform the vector in the world coordinates to this basis
+    pub fn local(&self, v: &Vec3) -> Vec3 {
+        Vec3::new(v.dot(&self.u), v.dot(&self.v), v.dot(&self.w))
+    }
+
+    // Transform the vector in this basis to the world coordinates
+    pub fn transform(&self, v: &Vec3) -> Vec3 {
+        v.x() * self.u + v.y() * self.v + v.z() * self.w
+    }
+}
+
+// Random direction with cosine distribution around the z axis
+pub fn random_cosine_direction(rng: &mut SampleRng) -> Vec3 {
+    let r1: f64 = rng.random_range(0.0..1.0);
+    let r2: f64 = rng.random_range(0.0..1.0);
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+    let z = (1.0 - r2).sqrt();
+    Vec3::new(x, y, z)
+}
+
+// Probability density functions of the scattering directions of materials. See "Ray Tracing: The Rest of Your Life".
+pub enum Pdf {
+    // Cosine-weighted distribution around the normal. This is the ideal distribution for Lambertian surfaces.
+    Cosine(Onb),
+    // Uniform distribution over the unit sphere
+    Sphere,
+    // Mixture of the cosine distribution for the diffuse lobe and the GGX distribution of visible normals for the
+    // specular lobe, which is chosen with the probability `specular`. `view` is the unit vector towards the viewer in
+    // the local coordinates of `onb`.
+    Microfacet {
+        onb: Onb,
+        view: Vec3,
+        alpha: f64,
+        specular: f64,
+    },
+}
+
+impl Pdf {
+    pub fn cosine(normal: &Vec3) -> Self {
+        Self::Cosine(Onb::new(normal))
+    }
+
+    pub fn value(&self, direction: &Vec3) -> f64 {
+        match self {
+            Self::Cosine(onb) => (direction.unit().dot(onb.w()) / PI).max(0.0),
+            Self::Sphere => 1.0 / (4.0 * PI),
+            Self::Microfacet {
+                onb,
+                view,
+                alpha,
+                specular,
+            } => {
+                let l = onb.local(&direction.unit());
+                let diffuse = (l.z() / PI).max(0.0);
+                specular * microfacet::reflection_pdf(view, &l, *alpha) + (1.0 - specular) * diffuse
+            }
+        }
+    }
+
+    pub fn generate(&self, rng: &mut SampleRng) -> Vec3 {
+        match self {
+            Self::Cosine(onb) => onb.transform(&random_cosine_direction(rng)),
+            Self::Sphere => Vec3::random_unit(rng),
+            Self::Microfacet {
+                onb,
+                view,
+                alpha,
+                specular,
+            } => {
+                if rng.random_range(0.0..1.0) < *specular {
+                    let h = microfacet::sample_visible_normal(view, *alpha, rng);
+                    onb.transform(&(-*view).reflect(&h))
+                } else {
+                    onb.transform(&random_cosine_direction(rng))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use minifb::{Key, KeyRepeat, MouseButton, MouseMode, Window, WindowOptions};
+use std::io;
+
+// Degrees the camera orbits by one key press
+const ORBIT_STEP: f64 = 5.0;
+
+pub const HELP: &str = "Keys: arrows orbit, W/S move closer/farther, -/= change vfov, [/] change defocus angle, \
+                        click focuses on the pixel, Enter saves the image, Esc quits";
+
+// User input on the preview window
+pub enum Input {
+    Orbit { yaw: f64, pitch: f64 }, // Degrees to rotate the camera around `lookat`
+    Dolly(f64),                     // Factor of the distance between `lookfrom` and `lookat`
+    Fov(f64),                       // Degrees added to `vfov`
+    Defocus(f64),                   // Degrees added to `defocus_angle`
+    Focus(u32, u32),                // Pixel clicked to focus on
+    Save,
+    Quit,
+}
+
+// Window showing the image being refined
+pub struct Preview {
+    window: Window,
+    buffer: Vec<u32>,
+    width: usize,
+    height: usize,
+    mouse_down: bool,
+}
+
+impl Preview {
+    pub fn new(width: u32, height: u32) -> io::Result<Self> {
+        let (width, height) = (width as usize, height as usize);
+        let mut window =
+            Window::new("raytracing", width, height, WindowOptions::default()).map_err(io::Error::other)?;
+        window.set_target_fps(60);
+        Ok(Self {
+            window,
+            buffer: vec![0; width * height],
+            width,
+            height,
+            mouse_down: false,
+        })
+    }
+
+    // Show the pixels in row-major order and process the events of the window
+    pub fn show(&mut self, pixels: impl Iterator<Item = (u8, u8, u8)>, title: &str) -> io::Result<()> {
+        for (dst, (r, g, b)) in self.buffer.iter_mut().zip(pixels) {
+            *dst = (r as u32) << 16 | (g as u32) << 8 | b as u32;
+        }
+        self.window.set_title(title);
+        self.window
+            .update_with_buffer(&self.buffer, self.width, self.height)
+            .map_err(io::Error::other)
+    }
+
+    // Inputs since the last call. `Quit` is also returned when the window was closed
+    pub fn inputs(&mut self) -> Vec<Input> {
+        if !self.window.is_open() {
+            return vec![Input::Quit];
+        }
+
+        let mut inputs: Vec<_> = self
+            .window
+            .get_keys_pressed(KeyRepeat::Yes)
+            .into_iter()
+            .filter_map(|key| match key {
+                Key::Left => Some(Input::Orbit {
+                    yaw: -ORBIT_STEP,
+                    pitch: 0.0,
+                }),
+                Key::Right => Some(Input::Orbit {
+                    yaw: ORBIT_STEP,
+                    pitch: 0.0,
+                }),
+                Key::Up => Some(Input::Orbit {
+                    yaw: 0.0,
+                    pitch: ORBIT_STEP,
+                }),
+                Key::Down => Some(Input::Orbit {
+                    yaw: 0.0,
+                    pitch: -ORBIT_STEP,
+                }),
+                Key::W => Some(Input::Dolly(0.9)),
+                Key::S => Some(Input::Dolly(1.0 / 0.9)),
+                Key::Minus => Some(Input::Fov(-2.0)),
+                Key::Equal => Some(Input::Fov(2.0)),
+                Key::LeftBracket => Some(Input::Defocus(-0.2)),
+                Key::RightBracket => Some(Input::Defocus(0.2)),
+                Key::Enter => Some(Input::Save),
+                Key::Escape => Some(Input::Quit),
+                _ => None,
+            })
+            .collect();
+
+        // Focus when the button is pressed, not while it is held
+        let down = self.window.get_mouse_down(MouseButton::Left);
+        if down
+            && !self.mouse_down
+            && let Some((x, y)) = self.window.get_mouse_pos(MouseMode::Discard)
+        {
+            inputs.push(Input::Focus(x as u32, y as u32));
+        }
+        self.mouse_down = down;
+
+        inputs
+    }
+}
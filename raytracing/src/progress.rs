@@ -0,0 +1,77 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+const INTERVAL: Duration = Duration::from_millis(200);
+
+// Progress line shown on stderr. This can be shared across threads.
+pub struct Progress {
+    visible: bool, // Nothing is printed when this is false
+    total: u64,
+    done: AtomicU64,
+    start: Instant,
+    last_print: Mutex<Instant>,
+}
+
+impl Progress {
+    pub fn new(total: u64, visible: bool) -> Self {
+        let start = Instant::now();
+        Self {
+            visible,
+            total,
+            done: AtomicU64::new(0),
+            start,
+            last_print: Mutex::new(start),
+        }
+    }
+
+    pub fn add(&self, n: u64) {
+        let done = self.done.fetch_add(n, Ordering::Relaxed) + n;
+        if !self.visible {
+            return;
+        }
+        // Skip printing when other thread is printing the line
+        let Ok(mut last_print) = self.last_print.try_lock() else {
+            return;
+        };
+        let now = Instant::now();
+        if now.duration_since(*last_print) >= INTERVAL {
+            *last_print = now;
+            self.print(done, now);
+        }
+    }
+
+    pub fn finish(&self) {
+        if !self.visible {
+            return;
+        }
+        let now = Instant::now();
+        self.print(self.done.load(Ordering::Relaxed), now);
+        eprintln!();
+    }
+
+    fn print(&self, done: u64, now: Instant) {
+        let elapsed = now.duration_since(self.start).as_secs_f64();
+        let ratio = if self.total == 0 {
+            1.0
+        } else {
+            done as f64 / self.total as f64
+        };
+        let eta = if ratio > 0.0 { elapsed / ratio - elapsed } else { 0.0 };
+        let mut stderr = io::stderr().lock();
+        let _ = write!(
+            stderr,
+            "\r{:5.1}% elapsed {} ETA {}",
+            ratio * 100.0,
+            format_secs(elapsed),
+            format_secs(eta),
+        );
+        let _ = stderr.flush();
+    }
+}
+
+fn format_secs(secs: f64) -> String {
+    let secs = secs.round() as u64;
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
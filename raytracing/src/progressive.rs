@@ -0,0 +1,134 @@
+use crate::vec3::Color;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+// Options for progressive rendering. Each pass traces one sample per pixel and accumulates it into a float buffer.
+pub struct Progressive {
+    pub every: u32,                  // Write a preview image (and a checkpoint) after every N passes
+    pub checkpoint: Option<PathBuf>, // Path to save the checkpoint
+    pub resume: bool,                // Continue the rendering from the checkpoint
+    pub scene: String,               // Name of the scene. Checkpoints of other scenes cannot be resumed
+}
+
+impl Default for Progressive {
+    fn default() -> Self {
+        Self {
+            every: 10,
+            checkpoint: None,
+            resume: false,
+            scene: String::new(),
+        }
+    }
+}
+
+const MAGIC: &[u8; 8] = b"RTCKPT03";
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// Settings of the rendering which must be the same to resume it from a checkpoint
+#[derive(Clone, PartialEq, Debug)]
+pub struct Settings {
+    pub width: u32,
+    pub height: u32,
+    pub seed: u64,
+    pub max_depth: u8,
+    pub scene: String,
+    pub options: u64, // Hash of the other options which change the samples, such as the camera and the projection
+}
+
+// Accumulated sums of samples for each pixel. This is saved to a file to resume the rendering later.
+pub struct Checkpoint {
+    pub settings: Settings,
+    pub passes: u32,
+    pub sums: Vec<Color>,
+}
+
+impl Checkpoint {
+    pub fn new(settings: Settings) -> Self {
+        let sums = vec![Color::ZERO; settings.width as usize * settings.height as usize];
+        Self {
+            settings,
+            passes: 0,
+            sums,
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        fn read_bytes<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+            let mut buf = [0; N];
+            r.read_exact(&mut buf)?;
+            Ok(buf)
+        }
+
+        let file = File::open(path)?;
+        let file_len = file.metadata()?.len();
+        let mut r = BufReader::new(file);
+        if &read_bytes::<8>(&mut r)? != MAGIC {
+            return Err(invalid(format!("{path:?} is not a checkpoint file")));
+        }
+        let width = u32::from_le_bytes(read_bytes(&mut r)?);
+        let height = u32::from_le_bytes(read_bytes(&mut r)?);
+        let seed = u64::from_le_bytes(read_bytes(&mut r)?);
+        let [max_depth] = read_bytes(&mut r)?;
+        let scene_len = u16::from_le_bytes(read_bytes(&mut r)?) as usize;
+        let mut scene = vec![0; scene_len];
+        r.read_exact(&mut scene)?;
+        let scene = String::from_utf8(scene).map_err(|_| invalid(format!("scene name in {path:?} is not UTF-8")))?;
+        let options = u64::from_le_bytes(read_bytes(&mut r)?);
+        let passes = u32::from_le_bytes(read_bytes(&mut r)?);
+
+        // Check the size before allocating the buffer so that a corrupt header does not exhaust the memory
+        let header_len = (8 + 4 + 4 + 8 + 1 + 2 + scene_len + 8 + 4) as u64;
+        let count = width as u64 * height as u64;
+        if count.checked_mul(24).and_then(|n| n.checked_add(header_len)) != Some(file_len) {
+            return Err(invalid(format!(
+                "size of {path:?} does not match {width}x{height} pixels"
+            )));
+        }
+        let mut sums = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let x = f64::from_le_bytes(read_bytes(&mut r)?);
+            let y = f64::from_le_bytes(read_bytes(&mut r)?);
+            let z = f64::from_le_bytes(read_bytes(&mut r)?);
+            sums.push(Color::new(x, y, z));
+        }
+        let settings = Settings {
+            width,
+            height,
+            seed,
+            max_depth,
+            scene,
+            options,
+        };
+        Ok(Self { settings, passes, sums })
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        // Write to a temporary file and rename it so that the previous checkpoint survives when the process is
+        // killed while saving.
+        let tmp = path.with_extension("tmp");
+        {
+            let mut w = BufWriter::new(File::create(&tmp)?);
+            let settings = &self.settings;
+            w.write_all(MAGIC)?;
+            w.write_all(&settings.width.to_le_bytes())?;
+            w.write_all(&settings.height.to_le_bytes())?;
+            w.write_all(&settings.seed.to_le_bytes())?;
+            w.write_all(&[settings.max_depth])?;
+            w.write_all(&(settings.scene.len() as u16).to_le_bytes())?;
+            w.write_all(settings.scene.as_bytes())?;
+            w.write_all(&settings.options.to_le_bytes())?;
+            w.write_all(&self.passes.to_le_bytes())?;
+            for c in &self.sums {
+                w.write_all(&c.x().to_le_bytes())?;
+                w.write_all(&c.y().to_le_bytes())?;
+                w.write_all(&c.z().to_le_bytes())?;
+            }
+            w.flush()?;
+        }
+        fs::rename(tmp, path)
+    }
+}
@@ -1,12 +1,41 @@
 use crate::interval::Interval;
-use crate::object::{Face, Hittable};
+use crate::material::Scatter;
+use crate::object::Face;
+use crate::pdf::Pdf;
+use crate::rng::SampleRng;
+use crate::stats;
 use crate::vec3::{Color, Point3, Vec3};
+use crate::world::World;
+use rand::Rng;
+
+// Number of bounces before starting Russian roulette
+const ROULETTE_MIN_BOUNCES: u8 = 3;
+
+// Multiple importance sampling. Sample the direction from the mixture of the material's PDF and the PDF towards the
+// lights and the environment map. Weighting the sample by the mixture PDF is the balance heuristic of one-sample MIS.
+// Returns the direction and the mixture PDF, or `None` when the direction cannot be sampled.
+fn sample_direction(pdf: &Pdf, world: &World, origin: &Point3, rng: &mut SampleRng) -> Option<(Vec3, f64)> {
+    let has_lights = world.has_lights();
+    let direction = if !has_lights || rng.random_bool(0.5) {
+        pdf.generate(rng)
+    } else {
+        world.random_light(origin, rng)
+    };
+    let pdf_value = if has_lights {
+        0.5 * pdf.value(&direction) + 0.5 * world.light_pdf_value(origin, &direction)
+    } else {
+        pdf.value(&direction)
+    };
+    (pdf_value > 0.0).then_some((direction, pdf_value))
+}
 
 #[derive(Default)]
 pub struct Ray {
     orig: Point3,
     dir: Vec3,
-    time: f64, // The time when the ray is generated
+    inv_dir: Vec3, // Inverse of each component of `dir`. This is precomputed for testing many bounding boxes
+    time: f64,     // The time when the ray is generated
+    wavelength: Option<f64>, // Wavelength in nanometers carried by the path in spectral rendering
 }
 
 impl Ray {
@@ -15,7 +44,19 @@ impl Ray {
     }
 
     pub fn new_at(time: f64, orig: Point3, dir: Vec3) -> Self {
-        Self { orig, dir, time }
+        let inv_dir = Vec3::new(1.0 / dir.x(), 1.0 / dir.y(), 1.0 / dir.z());
+        Self {
+            orig,
+            dir,
+            inv_dir,
+            time,
+            wavelength: None,
+        }
+    }
+
+    pub fn with_wavelength(mut self, wavelength: Option<f64>) -> Self {
+        self.wavelength = wavelength;
+        self
     }
 
     pub fn origin(&self) -> &Point3 {
@@ -26,31 +67,81 @@ impl Ray {
         &self.dir
     }
 
+    pub fn inv_direction(&self) -> &Vec3 {
+        &self.inv_dir
+    }
+
     pub fn time(&self) -> f64 {
         self.time
     }
 
+    pub fn wavelength(&self) -> Option<f64> {
+        self.wavelength
+    }
+
     pub fn at(&self, t: f64) -> Point3 {
         self.orig + t * self.dir
     }
 
-    pub fn color<H: Hittable>(&self, depth: u8, world: &H) -> Color {
-        if depth == 0 {
-            return Color::ZERO;
-        }
+    pub fn color(&self, max_depth: u8, world: &World, rng: &mut SampleRng) -> Color {
+        let mut radiance = Color::ZERO;
+        let mut throughput = Color::new(1.0, 1.0, 1.0); // Product of attenuations along the path so far
+        let mut ray = Ray::new_at(self.time, self.orig, self.dir).with_wavelength(self.wavelength);
+        let mut rays = 0;
+
+        for bounce in 0..max_depth {
+            rays += 1;
+            // Note: Use 0.001 to avoid the ray reflects just after the diffusion due to floating point round error.
+            let Some(hit) = world.hit(&ray, Interval::new(0.001, f64::INFINITY), rng) else {
+                radiance += throughput * world.background.color(&ray);
+                break;
+            };
 
-        // Note: Use 0.001 to avoid the ray reflects just after the diffusion due to floating point round error.
-        if let Some(hit) = world.hit(self, Interval::new(0.001, f64::INFINITY)) {
-            if let Some((scattered, attenuation)) = hit.mat.scatter(self, &hit) {
-                return attenuation * scattered.color(depth - 1, world);
+            radiance += throughput * hit.mat.emitted(&ray, &hit);
+
+            let scattered = match hit.mat.scatter(&ray, &hit, rng) {
+                None => break,
+                Some(Scatter::Specular(scattered, attenuation)) => {
+                    throughput *= attenuation;
+                    scattered
+                }
+                Some(Scatter::Diffuse(attenuation, pdf)) => {
+                    let Some((direction, pdf_value)) = sample_direction(&pdf, world, &hit.pos, rng) else {
+                        break;
+                    };
+                    let scattering_pdf = hit.mat.scattering_pdf(&ray, &hit, &direction);
+                    throughput *= attenuation * (scattering_pdf / pdf_value);
+                    Ray::new_at(ray.time(), hit.pos, direction)
+                }
+                Some(Scatter::Glossy(pdf)) => {
+                    let Some((direction, pdf_value)) = sample_direction(&pdf, world, &hit.pos, rng) else {
+                        break;
+                    };
+                    throughput *= hit.mat.scattering(&ray, &hit, &direction) / pdf_value;
+                    Ray::new_at(ray.time(), hit.pos, direction)
+                }
+            };
+
+            // Russian roulette. Terminate the path randomly with the probability based on its throughput and
+            // compensate the survived path by the probability. This keeps the estimation unbiased while saving time
+            // on dim paths. `max_depth` remains as a safety cap.
+            if bounce >= ROULETTE_MIN_BOUNCES {
+                let survival = throughput.x().max(throughput.y()).max(throughput.z()).min(0.95);
+                if rng.random_range(0.0..1.0) >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
             }
-            return Color::ZERO;
+
+            // Scattered rays carry the same wavelength through the path
+            ray = scattered.with_wavelength(self.wavelength);
         }
 
-        // Background color is linear gradient
-        let u = self.direction().unit();
-        let a = 0.5 * (u.y() + 1.0);
-        (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+        stats::add(|c| {
+            c.paths += 1;
+            c.rays += rays;
+        });
+        radiance
     }
 
     pub fn face(&self, outward_normal: &Vec3) -> Face {
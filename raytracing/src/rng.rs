@@ -0,0 +1,31 @@
+use rand::SeedableRng;
+use rand_pcg::Pcg64Mcg;
+
+// PRNG used for rendering. PCG is chosen because its output is portable across platforms and versions, unlike
+// `rand::rngs::SmallRng`.
+pub type SampleRng = Pcg64Mcg;
+
+// SplitMix64 finalizer to scramble the inputs before seeding
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e3779b97f4a7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+    x ^ (x >> 31)
+}
+
+pub fn scene_rng(seed: u64) -> SampleRng {
+    SampleRng::seed_from_u64(mix(seed))
+}
+
+// Derive the RNG for the sample of the pixel. Since it only depends on (seed, pixel, sample), the rendered result
+// does not depend on the order of the pixels being rendered.
+pub fn sample_rng(seed: u64, pixel: u32, sample: u32) -> SampleRng {
+    let key = ((pixel as u64) << 32) | sample as u64;
+    SampleRng::seed_from_u64(mix(mix(seed) ^ key))
+}
+
+// Derive the RNG for the values shared by all samples of the pixel. It is seeded in a different way from `sample_rng`
+// so that it does not reuse the stream of any sample.
+pub fn pixel_rng(seed: u64, pixel: u32) -> SampleRng {
+    SampleRng::seed_from_u64(mix(mix(!seed) ^ pixel as u64))
+}
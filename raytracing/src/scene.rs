@@ -0,0 +1,497 @@
+use crate::animation::Keyframe;
+use crate::bvh::BvhBuilder;
+use crate::camera::Camera;
+use crate::instance::{Moving, RotateY, Transformed, Translate};
+use crate::material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal, Principled};
+use crate::medium::ConstantMedium;
+use crate::object::Sphere;
+use crate::rng::scene_rng;
+use crate::shapes::{Cuboid, Cylinder, Disk, Plane, Quad};
+use crate::spectrum::Dispersion;
+use crate::texture::{CheckerTexture, SolidColor};
+use crate::transform::{Pose, Transform};
+use crate::vec3::{Color, Point3, Vec3};
+use crate::world::{Background, World};
+use rand::Rng;
+use std::sync::Arc;
+
+#[derive(Clone, Copy)]
+pub enum Scene {
+    Demo,
+    Checker,
+    Instances,
+    Fog,
+    Lights,
+    Materials,
+    Shapes,
+    Motion,
+    Dispersion,
+}
+
+impl Scene {
+    // Name of the scene in the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Demo => "demo",
+            Self::Checker => "checker",
+            Self::Instances => "instances",
+            Self::Fog => "fog",
+            Self::Lights => "lights",
+            Self::Materials => "materials",
+            Self::Shapes => "shapes",
+            Self::Motion => "motion",
+            Self::Dispersion => "dispersion",
+        }
+    }
+
+    pub fn world(self, cam: &mut Camera) -> World {
+        match self {
+            Self::Demo => demo_scene(cam),
+            Self::Checker => checker_scene(cam),
+            Self::Instances => instances_scene(cam),
+            Self::Fog => fog_scene(cam),
+            Self::Lights => lights_scene(cam),
+            Self::Materials => materials_scene(cam),
+            Self::Shapes => shapes_scene(cam),
+            Self::Motion => motion_scene(cam),
+            Self::Dispersion => dispersion_scene(cam),
+        }
+    }
+}
+
+// Huge sphere under the origin which most scenes stand on
+fn ground<M: Material>(mat: M) -> Sphere<M> {
+    Sphere::stationary(Point3::new(0.0, -1000.0, 0.0), 1000.0, mat)
+}
+
+// Ground with the dark blue and light gray checker. `scale` is the size of the squares
+fn checker_ground(scale: f64) -> Sphere<impl Material> {
+    ground(Lambertian::new(CheckerTexture::solid(
+        scale,
+        Color::new(0.1, 0.1, 0.2),
+        Color::new(0.7, 0.7, 0.7),
+    )))
+}
+
+fn demo_scene(cam: &mut Camera) -> World {
+    cam.vfov = 20.0;
+    cam.lookfrom = Point3::new(13.0, 2.0, 3.0);
+    cam.lookat = Point3::new(0.0, 0.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.defocus_angle = 0.6;
+    cam.focus_distance = 10.0;
+
+    let mut rng = scene_rng(cam.seed);
+    let mut builder = BvhBuilder::default();
+
+    builder.add(checker_ground(0.32));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = {
+                let x = a as f64 + rng.random_range(0.0..0.9);
+                let z = b as f64 + rng.random_range(0.0..0.9);
+                Point3::new(x, 0.2, z)
+            };
+
+            if (center - Point3::new(4.0, 0.2, 0.0)).length() <= 0.9 {
+                continue;
+            }
+
+            let random = rng.random_range(0.0..1.0);
+            if random < 0.8 {
+                // Diffuse
+                let albedo = Color::random(&mut rng, 0.0..1.0) * Color::random(&mut rng, 0.0..1.0);
+                let center_end = center + Vec3::new(0.0, rng.random_range(0.0..0.5), 0.0);
+                let material = Lambertian::solid(albedo);
+                let sphere = if random < 0.3 {
+                    Sphere::moving(center, center_end, 0.2, material)
+                } else {
+                    Sphere::stationary(center, 0.2, material)
+                };
+                builder.add(sphere);
+            } else if random < 0.95 {
+                // Metal
+                let albedo = Color::random(&mut rng, 0.5..1.0);
+                let fuzz = rng.random_range(0.0..0.5);
+                let sphere = Sphere::stationary(center, 0.2, Metal::new(albedo, fuzz));
+                builder.add(sphere);
+            } else {
+                // Glass
+                let sphere = Sphere::stationary(center, 0.2, Dielectric::new(1.5));
+                builder.add(sphere);
+            }
+        }
+    }
+
+    builder.add(Sphere::stationary(
+        Point3::new(0.0, 1.0, 0.0),
+        1.0,
+        Dielectric::new(1.5),
+    ));
+    builder.add(Sphere::stationary(
+        Point3::new(-4.0, 1.0, 0.0),
+        1.0,
+        Lambertian::solid(Color::new(0.4, 0.2, 0.1)),
+    ));
+    builder.add(Sphere::stationary(
+        Point3::new(4.0, 1.0, 0.0),
+        1.0,
+        Metal::new(Color::new(0.7, 0.6, 0.5), 0.0),
+    ));
+
+    World::new(builder.build())
+}
+
+fn checker_scene(cam: &mut Camera) -> World {
+    cam.vfov = 20.0;
+    cam.lookfrom = Point3::new(13.0, 2.0, 3.0);
+    cam.lookat = Point3::new(0.0, 0.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.defocus_angle = 0.0;
+
+    let mut builder = BvhBuilder::default();
+
+    let tex = CheckerTexture::solid(0.03, Color::new(0.1, 0.1, 0.2), Color::new(0.7, 0.7, 0.7));
+    let mat = Lambertian::new(tex);
+    builder.add(Sphere::stationary(Point3::new(0.0, -10.0, 0.0), 10.0, mat.clone()));
+    builder.add(Sphere::stationary(Point3::new(0.0, 10.0, 0.0), 10.0, mat));
+
+    World::new(builder.build())
+}
+
+fn instances_scene(cam: &mut Camera) -> World {
+    cam.vfov = 30.0;
+    cam.lookfrom = Point3::new(0.0, 6.0, 12.0);
+    cam.lookat = Point3::new(0.0, 0.5, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.defocus_angle = 0.0;
+
+    let mut builder = BvhBuilder::default();
+
+    builder.add(checker_ground(0.32));
+
+    // The same cluster of spheres is shared by all instances placed around the center
+    let cluster = {
+        let mut builder = BvhBuilder::default();
+        builder.add(Sphere::stationary(
+            Point3::new(0.0, 0.5, 0.0),
+            0.5,
+            Lambertian::solid(Color::new(0.8, 0.3, 0.3)),
+        ));
+        builder.add(Sphere::stationary(
+            Point3::new(0.6, 0.25, 0.0),
+            0.25,
+            Metal::new(Color::new(0.8, 0.8, 0.8), 0.1),
+        ));
+        builder.add(Sphere::stationary(
+            Point3::new(0.0, 0.2, 0.6),
+            0.2,
+            Dielectric::new(1.5),
+        ));
+        Arc::new(builder.build())
+    };
+    for i in 0..8 {
+        let degrees = i as f64 * 45.0;
+        let rotated = RotateY::new(cluster.clone(), degrees);
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        builder.add(Translate::new(rotated, Vec3::new(4.0 * cos, 0.0, 4.0 * sin)));
+    }
+
+    // Ellipsoid at the center made by scaling and tilting a unit sphere
+    let transform = Transform::scale(Vec3::new(1.5, 0.75, 0.75))
+        .then(&Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 30.0))
+        .then(&Transform::translate(Vec3::new(0.0, 1.25, 0.0)));
+    let sphere = Sphere::stationary(Point3::ZERO, 1.0, Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    builder.add(Transformed::new(sphere, transform));
+
+    World::new(builder.build())
+}
+
+fn fog_scene(cam: &mut Camera) -> World {
+    cam.vfov = 25.0;
+    cam.lookfrom = Point3::new(13.0, 2.0, 3.0);
+    cam.lookat = Point3::new(0.0, 0.8, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.defocus_angle = 0.0;
+
+    let mut builder = BvhBuilder::default();
+
+    builder.add(checker_ground(0.32));
+
+    // Glass ball filled with blue subsurface scattering volume
+    let center = Point3::new(0.0, 1.0, -1.5);
+    builder.add(Sphere::stationary(center, 1.0, Dielectric::new(1.5)));
+    builder.add(ConstantMedium::new(
+        Sphere::stationary(center, 0.99, Lambertian::solid(Color::ZERO)),
+        2.0,
+        Isotropic::solid(Color::new(0.2, 0.4, 0.9)),
+    ));
+
+    // Cloud of white smoke
+    builder.add(ConstantMedium::new(
+        Sphere::stationary(Point3::new(0.0, 1.0, 1.5), 1.0, Lambertian::solid(Color::ZERO)),
+        1.0,
+        Isotropic::solid(Color::new(1.0, 1.0, 1.0)),
+    ));
+
+    // Thin mist covering the entire scene including the camera
+    builder.add(ConstantMedium::new(
+        Sphere::stationary(Point3::ZERO, 50.0, Lambertian::solid(Color::ZERO)),
+        0.01,
+        Isotropic::solid(Color::new(1.0, 1.0, 1.0)),
+    ));
+
+    World::new(builder.build())
+}
+
+fn lights_scene(cam: &mut Camera) -> World {
+    cam.vfov = 20.0;
+    cam.lookfrom = Point3::new(26.0, 3.0, 6.0);
+    cam.lookat = Point3::new(0.0, 2.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.defocus_angle = 0.0;
+
+    let mut builder = BvhBuilder::default();
+
+    builder.add(checker_ground(0.32));
+    builder.add(Sphere::stationary(
+        Point3::new(0.0, 2.0, 0.0),
+        2.0,
+        Lambertian::solid(Color::new(0.4, 0.2, 0.1)),
+    ));
+    builder.add(Sphere::stationary(
+        Point3::new(0.0, 2.0, 4.5),
+        1.5,
+        Metal::new(Color::new(0.7, 0.6, 0.5), 0.2),
+    ));
+
+    // Small and bright light sources. They are hard to hit by random scattering
+    let mut lights = vec![];
+    for (center, radius, emit) in [
+        (Point3::new(0.0, 7.0, 0.0), 0.5, Color::new(40.0, 40.0, 40.0)),
+        (Point3::new(3.0, 3.0, -3.0), 0.3, Color::new(40.0, 20.0, 10.0)),
+    ] {
+        let light = Arc::new(Sphere::stationary(center, radius, DiffuseLight::solid(emit)));
+        builder.add(light.clone());
+        lights.push(light);
+    }
+
+    let mut world = World::new(builder.build());
+    for light in lights {
+        world.lights.add(light);
+    }
+    world.background = Background::Solid(Color::ZERO);
+    world
+}
+
+fn materials_scene(cam: &mut Camera) -> World {
+    cam.vfov = 25.0;
+    cam.lookfrom = Point3::new(0.0, 3.0, 9.0);
+    cam.lookat = Point3::new(0.0, 0.4, -0.6);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.defocus_angle = 0.0;
+
+    let mut builder = BvhBuilder::default();
+
+    // Gray ground whose roughness is driven by a checker texture
+    builder.add(ground(Principled::new(
+        SolidColor::new(Color::new(0.5, 0.5, 0.5)),
+        CheckerTexture::solid(0.32, Color::new(0.1, 0.1, 0.1), Color::new(0.7, 0.7, 0.7)),
+        SolidColor::new(Color::ZERO),
+    )));
+
+    // Roughness increases from left to right. The front row is dielectric and the back row is metallic
+    for i in 0..5 {
+        let x = i as f64 - 2.0;
+        let roughness = i as f64 / 4.0;
+        builder.add(Sphere::stationary(
+            Point3::new(x, 0.4, 0.0),
+            0.4,
+            Principled::solid(Color::new(0.8, 0.1, 0.1), roughness, 0.0),
+        ));
+        builder.add(Sphere::stationary(
+            Point3::new(x, 0.4, -1.2),
+            0.4,
+            Principled::solid(Color::new(1.0, 0.78, 0.34), roughness, 1.0),
+        ));
+    }
+
+    let light = Arc::new(Sphere::stationary(
+        Point3::new(-3.0, 5.0, 3.0),
+        1.0,
+        DiffuseLight::solid(Color::new(8.0, 8.0, 8.0)),
+    ));
+    builder.add(light.clone());
+
+    let mut world = World::new(builder.build());
+    world.lights.add(light);
+    world
+}
+
+fn shapes_scene(cam: &mut Camera) -> World {
+    cam.vfov = 30.0;
+    cam.lookfrom = Point3::new(6.0, 4.0, 10.0);
+    cam.lookat = Point3::new(0.0, 1.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.defocus_angle = 0.0;
+
+    let mut builder = BvhBuilder::default();
+    builder.add(Cuboid::new(
+        Point3::new(-3.0, 0.0, -1.0),
+        Point3::new(-1.5, 2.5, 0.5),
+        Lambertian::solid(Color::new(0.7, 0.3, 0.2)),
+    ));
+    builder.add(Cylinder::new(
+        Point3::new(0.5, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0.8,
+        1.8,
+        Principled::solid(Color::new(0.9, 0.9, 0.9), 0.3, 1.0),
+    ));
+    builder.add(Cylinder::new(
+        Point3::new(1.5, 0.4, 2.5),
+        Vec3::new(1.0, 0.0, -0.5),
+        0.4,
+        1.5,
+        Lambertian::solid(Color::new(0.2, 0.4, 0.8)),
+    ));
+    builder.add(Disk::new(
+        Point3::new(3.0, 1.2, -1.5),
+        Vec3::new(-1.0, 0.3, 1.0),
+        1.2,
+        Lambertian::new(CheckerTexture::solid(
+            0.1,
+            Color::new(0.9, 0.8, 0.2),
+            Color::new(0.2, 0.2, 0.2),
+        )),
+    ));
+
+    // Area light above the objects facing down
+    let light = Arc::new(Quad::new(
+        Point3::new(-1.0, 6.0, -1.0),
+        Vec3::new(0.0, 0.0, 2.0),
+        Vec3::new(2.0, 0.0, 0.0),
+        DiffuseLight::solid(Color::new(10.0, 10.0, 10.0)),
+    ));
+    builder.add(light.clone());
+
+    let mut world = World::new(builder.build());
+    world.unbounded.push(Arc::new(Plane::new(
+        Point3::ZERO,
+        Vec3::new(0.0, 1.0, 0.0),
+        Lambertian::new(CheckerTexture::solid(
+            1.0,
+            Color::new(0.1, 0.1, 0.2),
+            Color::new(0.7, 0.7, 0.7),
+        )),
+    )));
+    world.lights.add(light);
+    world.background = Background::Solid(Color::new(0.1, 0.1, 0.15));
+    world
+}
+
+fn motion_scene(cam: &mut Camera) -> World {
+    cam.vfov = 30.0;
+    cam.lookfrom = Point3::new(0.0, 3.0, 10.0);
+    cam.lookat = Point3::new(0.0, 1.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.defocus_angle = 0.0;
+    // The camera pans slightly to the right while the shutter is open
+    cam.lookfrom_end = Some(Point3::new(0.3, 3.0, 10.0));
+    // Animation flies around the objects from the left to the right
+    cam.keyframes = vec![
+        Keyframe::new(0.0, Point3::new(-8.0, 2.0, 6.0), Point3::new(-1.0, 1.0, 0.0)),
+        Keyframe::new(0.5, Point3::new(0.0, 4.0, 9.0), Point3::new(0.0, 1.0, 0.0)),
+        Keyframe::new(1.0, Point3::new(8.0, 2.0, 6.0), Point3::new(1.0, 1.0, 0.0)),
+    ];
+
+    let mut builder = BvhBuilder::default();
+    builder.add(checker_ground(0.5));
+
+    // Spinning box
+    let unit = Vec3::new(1.0, 1.0, 1.0);
+    let up = Vec3::new(0.0, 1.0, 0.0);
+    let cube = Cuboid::new(
+        Point3::new(-0.7, -0.7, -0.7),
+        Point3::new(0.7, 0.7, 0.7),
+        Lambertian::solid(Color::new(0.7, 0.3, 0.2)),
+    );
+    let from = Pose::new(unit, up, 0.0, Vec3::new(-2.5, 0.7, 0.0));
+    let to = Pose::new(unit, up, 60.0, Vec3::new(-2.5, 0.7, 0.0));
+    builder.add(Moving::new(cube, from, to));
+
+    // Cylinder moving to the right while tilting
+    let cylinder = Cylinder::new(
+        Point3::ZERO,
+        up,
+        0.5,
+        1.6,
+        Principled::solid(Color::new(0.9, 0.9, 0.9), 0.3, 1.0),
+    );
+    let from = Pose::translate(Vec3::new(-0.3, 0.0, 0.0));
+    let to = Pose::new(unit, Vec3::new(0.0, 0.0, 1.0), -20.0, Vec3::new(0.8, 0.0, 0.0));
+    builder.add(Moving::new(cylinder, from, to));
+
+    // Sphere bouncing once while it grows
+    let sphere = Sphere::stationary(Point3::ZERO, 1.0, Lambertian::solid(Color::new(0.2, 0.4, 0.8)));
+    let keys = vec![
+        (0.0, Pose::new(0.5 * unit, up, 0.0, Vec3::new(2.5, 0.5, 0.0))),
+        (0.5, Pose::new(0.6 * unit, up, 0.0, Vec3::new(2.5, 1.5, 0.0))),
+        (1.0, Pose::new(0.7 * unit, up, 0.0, Vec3::new(2.5, 0.7, 0.0))),
+    ];
+    builder.add(Moving::keyframes(sphere, keys));
+
+    World::new(builder.build())
+}
+
+fn dispersion_scene(cam: &mut Camera) -> World {
+    cam.vfov = 25.0;
+    cam.lookfrom = Point3::new(0.0, 1.5, 8.0);
+    cam.lookat = Point3::new(0.0, 1.0, 0.0);
+    cam.vup = Vec3::new(0.0, 1.0, 0.0);
+    cam.defocus_angle = 0.0;
+
+    let mut builder = BvhBuilder::default();
+
+    // Stripes behind the glass show how much each color is bent
+    for i in -6..=6 {
+        builder.add(Quad::new(
+            Point3::new(i as f64 * 0.5 - 0.05, -1.0, -4.0),
+            Vec3::new(0.1, 0.0, 0.0),
+            Vec3::new(0.0, 5.0, 0.0),
+            DiffuseLight::solid(Color::new(4.0, 4.0, 4.0)),
+        ));
+    }
+    builder.add(Quad::new(
+        Point3::new(-5.0, -1.0, -4.01),
+        Vec3::new(10.0, 0.0, 0.0),
+        Vec3::new(0.0, 5.0, 0.0),
+        Lambertian::solid(Color::new(0.05, 0.05, 0.05)),
+    ));
+
+    // Flint glass ball and a crown glass cube standing on its edge. The cube acts as a prism near its corners
+    builder.add(Sphere::stationary(
+        Point3::new(-1.1, 1.0, 0.0),
+        0.9,
+        Dielectric::dispersive(Dispersion::SF11),
+    ));
+    let transform = Transform::rotate(Vec3::new(0.0, 0.0, 1.0), 45.0)
+        .then(&Transform::rotate(Vec3::new(0.0, 1.0, 0.0), 20.0))
+        .then(&Transform::translate(Vec3::new(1.2, 1.0, 0.0)));
+    let cube = Cuboid::new(
+        Point3::new(-0.6, -0.6, -0.6),
+        Point3::new(0.6, 0.6, 0.6),
+        Dielectric::dispersive(Dispersion::BK7),
+    );
+    builder.add(Transformed::new(cube, transform));
+
+    let mut world = World::new(builder.build());
+    world.unbounded.push(Arc::new(Plane::new(
+        Point3::ZERO,
+        Vec3::new(0.0, 1.0, 0.0),
+        Lambertian::solid(Color::new(0.5, 0.5, 0.5)),
+    )));
+    world.background = Background::Solid(Color::ZERO);
+    world
+}
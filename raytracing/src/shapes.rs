@@ -0,0 +1,454 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::material::Material;
+use crate::object::{Face, Hit, Hittable, Light};
+use crate::pdf::Onb;
+use crate::ray::Ray;
+use crate::rng::SampleRng;
+use crate::vec3::{Point3, Vec3};
+use rand::Rng;
+use std::f64::consts::PI;
+
+// Rays nearly parallel to a plane are treated as missing it
+const PARALLEL_EPSILON: f64 = 1e-8;
+
+fn make_hit<'a>(ray: &Ray, time: f64, outward_normal: Vec3, mat: &'a dyn Material, u: f64, v: f64) -> Hit<'a> {
+    let face = ray.face(&outward_normal);
+    let normal = match face {
+        Face::Front => outward_normal,
+        Face::Back => -outward_normal,
+    };
+    Hit {
+        pos: ray.at(time),
+        normal,
+        time,
+        face,
+        mat,
+        u,
+        v,
+    }
+}
+
+// Ray parameter where the ray crosses the plane through the point with the unit normal
+fn hit_plane(ray: &Ray, point: &Point3, normal: &Vec3, time: Interval) -> Option<f64> {
+    let denom = normal.dot(ray.direction());
+    if denom.abs() < PARALLEL_EPSILON {
+        return None;
+    }
+    let t = normal.dot(&(*point - *ray.origin())) / denom;
+    time.surrounds(t).then_some(t)
+}
+
+// Infinite plane. It has no finite bounding box so it must be added to `World::unbounded` instead of the BVH. `u` and
+// `v` are the coordinates on the plane in world units.
+pub struct Plane<M> {
+    point: Point3,
+    onb: Onb, // `w` is the normal
+    mat: M,
+}
+
+impl<M> Plane<M> {
+    pub fn new(point: Point3, normal: Vec3, mat: M) -> Self {
+        let onb = Onb::new(&normal);
+        Self { point, onb, mat }
+    }
+}
+
+impl<M: Material> Hittable for Plane<M> {
+    fn hit(&self, ray: &Ray, time: Interval, _rng: &mut SampleRng) -> Option<Hit<'_>> {
+        let t = hit_plane(ray, &self.point, self.onb.w(), time)?;
+        let local = self.onb.local(&(ray.at(t) - self.point));
+        Some(make_hit(ray, t, *self.onb.w(), &self.mat, local.x(), local.y()))
+    }
+
+    fn bbox(&self) -> Aabb {
+        let inf = Interval::new(f64::NEG_INFINITY, f64::INFINITY);
+        Aabb::from_axis(inf, inf, inf)
+    }
+}
+
+// Disk with the center, the normal and the radius. `u` is the angle around the normal and `v` is the distance from
+// the center, both in [0, 1].
+pub struct Disk<M> {
+    center: Point3,
+    radius: f64,
+    onb: Onb,
+    bbox: Aabb,
+    mat: M,
+}
+
+// Bounding box of the circle around the center perpendicular to the unit normal
+fn circle_bbox(center: &Point3, normal: &Vec3, radius: f64) -> Aabb {
+    let extent = |n: f64| radius * (1.0 - n * n).max(0.0).sqrt();
+    let e = Vec3::new(extent(normal.x()), extent(normal.y()), extent(normal.z()));
+    Aabb::from_extrema(*center - e, *center + e)
+}
+
+impl<M> Disk<M> {
+    pub fn new(center: Point3, normal: Vec3, radius: f64, mat: M) -> Self {
+        let onb = Onb::new(&normal);
+        let bbox = circle_bbox(&center, onb.w(), radius);
+        Self {
+            center,
+            radius,
+            onb,
+            bbox,
+            mat,
+        }
+    }
+
+    // Ray parameter of the hit and the hit point in the local coordinates where the normal is z
+    fn intersect(&self, ray: &Ray, time: Interval) -> Option<(f64, Vec3)> {
+        let t = hit_plane(ray, &self.center, self.onb.w(), time)?;
+        let local = self.onb.local(&(ray.at(t) - self.center));
+        (local.x() * local.x() + local.y() * local.y() <= self.radius * self.radius).then_some((t, local))
+    }
+}
+
+impl<M: Material> Hittable for Disk<M> {
+    fn hit(&self, ray: &Ray, time: Interval, _rng: &mut SampleRng) -> Option<Hit<'_>> {
+        let (t, local) = self.intersect(ray, time)?;
+        let r = (local.x() * local.x() + local.y() * local.y()).sqrt();
+        let u = (local.y().atan2(local.x()) + PI) / (2.0 * PI);
+        let v = r / self.radius;
+        Some(make_hit(ray, t, *self.onb.w(), &self.mat, u, v))
+    }
+
+    fn bbox(&self) -> Aabb {
+        self.bbox.clone()
+    }
+}
+
+impl<M: Material> Light for Disk<M> {
+    // Convert the uniform density over the area to the density in solid angle
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction);
+        let Some((t, _)) = self.intersect(&ray, Interval::new(0.001, f64::INFINITY)) else {
+            return 0.0;
+        };
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = (direction.dot(self.onb.w()) / direction.length()).abs();
+        distance_squared / (cosine * PI * self.radius * self.radius)
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut SampleRng) -> Vec3 {
+        let p = self.radius * Vec3::random_in_unit_circle(rng);
+        self.center + self.onb.transform(&p) - *origin
+    }
+}
+
+// Parallelogram spanned by `u` and `v` from the corner `q`. See 6 of "Ray Tracing: The Next Week".
+#[derive(Clone, Copy)]
+struct Parallelogram {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3, // Converts the hit point to the coordinates along `u` and `v`
+    normal: Vec3,
+    area: f64,
+}
+
+impl Parallelogram {
+    fn new(q: Point3, u: Vec3, v: Vec3) -> Self {
+        let n = u.cross(&v);
+        Self {
+            q,
+            u,
+            v,
+            w: n / n.length_squared(),
+            normal: n.unit(),
+            area: n.length(),
+        }
+    }
+
+    // Returns the ray parameter and the coordinates in [0, 1] along `u` and `v`
+    fn hit(&self, ray: &Ray, time: Interval) -> Option<(f64, f64, f64)> {
+        let t = hit_plane(ray, &self.q, &self.normal, time)?;
+        let p = ray.at(t) - self.q;
+        let alpha = self.w.dot(&p.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&p));
+        let unit = 0.0..=1.0;
+        (unit.contains(&alpha) && unit.contains(&beta)).then_some((t, alpha, beta))
+    }
+
+    fn bbox(&self) -> Aabb {
+        let diagonal1 = Aabb::from_extrema(self.q, self.q + self.u + self.v);
+        let diagonal2 = Aabb::from_extrema(self.q + self.u, self.q + self.v);
+        Aabb::new_contained(&diagonal1, &diagonal2)
+    }
+}
+
+// Flat quadrilateral (parallelogram). It can be sampled as a light.
+pub struct Quad<M> {
+    shape: Parallelogram,
+    bbox: Aabb,
+    mat: M,
+}
+
+impl<M> Quad<M> {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, mat: M) -> Self {
+        let shape = Parallelogram::new(q, u, v);
+        let bbox = shape.bbox();
+        Self { shape, bbox, mat }
+    }
+}
+
+impl<M: Material> Hittable for Quad<M> {
+    fn hit(&self, ray: &Ray, time: Interval, _rng: &mut SampleRng) -> Option<Hit<'_>> {
+        let (t, u, v) = self.shape.hit(ray, time)?;
+        Some(make_hit(ray, t, self.shape.normal, &self.mat, u, v))
+    }
+
+    fn bbox(&self) -> Aabb {
+        self.bbox.clone()
+    }
+}
+
+impl<M: Material> Light for Quad<M> {
+    // Convert the uniform density over the area to the density in solid angle
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction);
+        let Some((t, _, _)) = self.shape.hit(&ray, Interval::new(0.001, f64::INFINITY)) else {
+            return 0.0;
+        };
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = (direction.dot(&self.shape.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.shape.area)
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut SampleRng) -> Vec3 {
+        let s = &self.shape;
+        let p = s.q + rng.random_range(0.0..1.0) * s.u + rng.random_range(0.0..1.0) * s.v;
+        p - *origin
+    }
+}
+
+// Axis-aligned box made of 6 quads. `u` and `v` are the coordinates on each face.
+pub struct Cuboid<M> {
+    sides: [Parallelogram; 6],
+    bbox: Aabb,
+    mat: M,
+}
+
+impl<M> Cuboid<M> {
+    // Box with the two opposite corners
+    pub fn new(a: Point3, b: Point3, mat: M) -> Self {
+        let min = Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+
+        // The normals of the sides point outward
+        let sides = [
+            Parallelogram::new(Point3::new(min.x(), min.y(), max.z()), dx, dy), // Front
+            Parallelogram::new(Point3::new(max.x(), min.y(), max.z()), -dz, dy), // Right
+            Parallelogram::new(Point3::new(max.x(), min.y(), min.z()), -dx, dy), // Back
+            Parallelogram::new(Point3::new(min.x(), min.y(), min.z()), dz, dy), // Left
+            Parallelogram::new(Point3::new(min.x(), max.y(), max.z()), dx, -dz), // Top
+            Parallelogram::new(Point3::new(min.x(), min.y(), min.z()), dx, dz), // Bottom
+        ];
+        let bbox = Aabb::from_extrema(min, max);
+        Self { sides, bbox, mat }
+    }
+}
+
+impl<M: Material> Hittable for Cuboid<M> {
+    fn hit(&self, ray: &Ray, mut time: Interval, _rng: &mut SampleRng) -> Option<Hit<'_>> {
+        let mut closest = None;
+        for side in &self.sides {
+            if let Some((t, u, v)) = side.hit(ray, time) {
+                time.upper_bound(t);
+                closest = Some((t, side.normal, u, v));
+            }
+        }
+        let (t, normal, u, v) = closest?;
+        Some(make_hit(ray, t, normal, &self.mat, u, v))
+    }
+
+    fn bbox(&self) -> Aabb {
+        self.bbox.clone()
+    }
+}
+
+// Crossing of a ray with the surface of `Cylinder`: the ray parameter, the outward normal in the local coordinates and
+// the texture coordinates
+type Crossing = (f64, Vec3, f64, f64);
+
+// Cylinder closed by disks at both ends. It stands on the center of the base along the axis. On the side `u` is the
+// angle around the axis and `v` is the height, both in [0, 1]. The caps are mapped like `Disk`.
+pub struct Cylinder<M> {
+    base: Point3,
+    radius: f64,
+    height: f64,
+    onb: Onb, // `w` is the axis
+    bbox: Aabb,
+    mat: M,
+}
+
+impl<M> Cylinder<M> {
+    pub fn new(base: Point3, axis: Vec3, radius: f64, height: f64, mat: M) -> Self {
+        let onb = Onb::new(&axis);
+        let top = base + height * *onb.w();
+        let bbox = Aabb::new_contained(
+            &circle_bbox(&base, onb.w(), radius),
+            &circle_bbox(&top, onb.w(), radius),
+        );
+        Self {
+            base,
+            radius,
+            height,
+            onb,
+            bbox,
+            mat,
+        }
+    }
+
+    // Every crossing of the ray with the side and the caps in the interval. A ray crosses the closed surface at most
+    // twice, but each part is tested separately.
+    fn crossings(&self, ray: &Ray, time: Interval) -> [Option<Crossing>; 4] {
+        // Solve in the local coordinates where the axis is z and the base is the origin
+        let o = self.onb.local(&(*ray.origin() - self.base));
+        let d = self.onb.local(ray.direction());
+        let mut crossings = [None; 4];
+
+        // Side: x^2 + y^2 = r^2 and 0 <= z <= height
+        let a = d.x() * d.x() + d.y() * d.y();
+        let h = o.x() * d.x() + o.y() * d.y();
+        let c = o.x() * o.x() + o.y() * o.y() - self.radius * self.radius;
+        let discriminant = h * h - a * c;
+        if a > 0.0 && discriminant >= 0.0 {
+            let sqrtd = discriminant.sqrt();
+            for (crossing, t) in crossings[..2].iter_mut().zip([(-h - sqrtd) / a, (-h + sqrtd) / a]) {
+                let z = o.z() + t * d.z();
+                if time.surrounds(t) && (0.0..=self.height).contains(&z) {
+                    let p = o + t * d;
+                    let normal = Vec3::new(p.x(), p.y(), 0.0) / self.radius;
+                    let u = (p.y().atan2(p.x()) + PI) / (2.0 * PI);
+                    *crossing = Some((t, normal, u, z / self.height));
+                }
+            }
+        }
+
+        // Caps at z = 0 and z = height
+        if d.z().abs() >= PARALLEL_EPSILON {
+            let caps = [
+                (0.0, Vec3::new(0.0, 0.0, -1.0)),
+                (self.height, Vec3::new(0.0, 0.0, 1.0)),
+            ];
+            for (crossing, (z, normal)) in crossings[2..].iter_mut().zip(caps) {
+                let t = (z - o.z()) / d.z();
+                let p = o + t * d;
+                let r = (p.x() * p.x() + p.y() * p.y()).sqrt();
+                if time.surrounds(t) && r <= self.radius {
+                    let u = (p.y().atan2(p.x()) + PI) / (2.0 * PI);
+                    *crossing = Some((t, normal, u, r / self.radius));
+                }
+            }
+        }
+
+        crossings
+    }
+
+    fn area(&self) -> f64 {
+        2.0 * PI * self.radius * (self.height + self.radius)
+    }
+}
+
+impl<M: Material> Hittable for Cylinder<M> {
+    fn hit(&self, ray: &Ray, time: Interval, _rng: &mut SampleRng) -> Option<Hit<'_>> {
+        let (t, normal, u, v) = self
+            .crossings(ray, time)
+            .into_iter()
+            .flatten()
+            .min_by(|l, r| l.0.total_cmp(&r.0))?;
+        Some(make_hit(ray, t, self.onb.transform(&normal), &self.mat, u, v))
+    }
+
+    fn bbox(&self) -> Aabb {
+        self.bbox.clone()
+    }
+}
+
+impl<M: Material> Light for Cylinder<M> {
+    // The surface is sampled uniformly by area. A direction can reach up to two points on it, so the densities of
+    // all crossings are summed.
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction);
+        let crossings = self.crossings(&ray, Interval::new(0.001, f64::INFINITY));
+        crossings
+            .into_iter()
+            .flatten()
+            .map(|(t, normal, _, _)| {
+                let distance_squared = t * t * direction.length_squared();
+                let cosine = (direction.dot(&self.onb.transform(&normal)) / direction.length()).abs();
+                distance_squared / (cosine * self.area())
+            })
+            .sum()
+    }
+
+    fn random(&self, origin: &Point3, rng: &mut SampleRng) -> Vec3 {
+        let side = 2.0 * PI * self.radius * self.height;
+        let p = if rng.random_range(0.0..self.area()) < side {
+            let phi = rng.random_range(0.0..2.0 * PI);
+            let z = rng.random_range(0.0..self.height);
+            Vec3::new(self.radius * phi.cos(), self.radius * phi.sin(), z)
+        } else {
+            let z = if rng.random_bool(0.5) { 0.0 } else { self.height };
+            self.radius * Vec3::random_in_unit_circle(rng) + Vec3::new(0.0, 0.0, z)
+        };
+        self.base + self.onb.transform(&p) - *origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Lambertian;
+    use crate::rng::scene_rng;
+    use crate::vec3::Color;
+
+    // The solid angle of the light seen from the origin estimated by hitting it with uniform directions, and by the
+    // PDF of its own samples. They agree only when `Light::pdf_value` is the density of `Light::random`.
+    fn solid_angles(light: &impl Light, origin: Point3) -> (f64, f64) {
+        const N: usize = 200000;
+        let mut rng = scene_rng(1);
+        let forever = Interval::new(0.001, f64::INFINITY);
+        let hits = (0..N)
+            .filter(|_| {
+                let ray = Ray::new(origin, Vec3::random_unit(&mut rng));
+                light.hit(&ray, forever, &mut rng).is_some()
+            })
+            .count();
+        let uniform = 4.0 * PI * hits as f64 / N as f64;
+        let sampled = (0..N)
+            .map(|_| {
+                let direction = light.random(&origin, &mut rng);
+                1.0 / light.pdf_value(&origin, &direction)
+            })
+            .sum::<f64>()
+            / N as f64;
+        (uniform, sampled)
+    }
+
+    #[test]
+    fn light_pdfs() {
+        let mat = Lambertian::solid(Color::new(0.5, 0.5, 0.5));
+        let origin = Point3::new(0.3, 0.2, 2.0);
+        let quad = Quad::new(
+            Point3::new(-1.0, -0.5, 0.0),
+            Vec3::new(2.0, 0.0, 0.3),
+            Vec3::new(0.0, 1.0, 0.0),
+            mat.clone(),
+        );
+        let disk = Disk::new(Point3::ZERO, Vec3::new(0.2, 0.3, 1.0), 1.0, mat.clone());
+        let cylinder = Cylinder::new(Point3::new(0.0, -0.5, 0.0), Vec3::new(0.3, 1.0, 0.2), 0.6, 1.2, mat);
+        for (name, (uniform, sampled)) in [
+            ("quad", solid_angles(&quad, origin)),
+            ("disk", solid_angles(&disk, origin)),
+            ("cylinder", solid_angles(&cylinder, origin)),
+        ] {
+            assert!((uniform / sampled - 1.0).abs() < 0.02, "{name}: {uniform} vs {sampled}");
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use crate::vec3::Color;
+use std::sync::LazyLock;
+
+// Range of visible wavelengths in nanometers
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+// Wavelength of the sodium D line where the refractive index of glass is usually specified
+pub const LAMBDA_D: f64 = 587.6;
+
+// Piecewise Gaussian with different widths on each side of the mean
+fn gaussian(lambda: f64, mean: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+    let sigma = if lambda < mean { sigma_low } else { sigma_high };
+    let t = (lambda - mean) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 2° color matching functions. This is the multi-lobe fit of "Simple Analytic Approximations to the CIE XYZ
+// Color Matching Functions" (Wyman et al. 2013) instead of the tabulated data.
+fn cie_xyz(lambda: f64) -> Color {
+    let x = 1.056 * gaussian(lambda, 599.8, 37.9, 31.0) + 0.362 * gaussian(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * gaussian(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * gaussian(lambda, 568.8, 46.9, 40.5) + 0.286 * gaussian(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * gaussian(lambda, 437.0, 11.8, 36.0) + 0.681 * gaussian(lambda, 459.0, 26.0, 13.8);
+    Color::new(x, y, z)
+}
+
+// CIE XYZ to linear sRGB (D65)
+fn xyz_to_rgb(c: Color) -> Color {
+    Color::new(
+        3.2404542 * c.x() - 1.5371385 * c.y() - 0.4985314 * c.z(),
+        -0.9692660 * c.x() + 1.8760108 * c.y() + 0.0415560 * c.z(),
+        0.0556434 * c.x() - 0.2040259 * c.y() + 1.0572252 * c.z(),
+    )
+}
+
+// Integral of the RGB color matching functions over the visible range. Dividing by this maps the constant spectrum to
+// white.
+static RGB_INTEGRAL: LazyLock<Color> = LazyLock::new(|| {
+    let steps = (LAMBDA_MAX - LAMBDA_MIN) as u32;
+    (0..steps)
+        .map(|i| xyz_to_rgb(cie_xyz(LAMBDA_MIN + i as f64 + 0.5)))
+        .fold(Color::ZERO, |acc, c| acc + c)
+});
+
+// Sample a visible wavelength from `u` in [0, 1). The density roughly follows the luminous efficiency so that
+// wavelengths contributing more to the image are sampled more. See "An Improved Technique for Full Spectral Rendering"
+// (Radziszewski et al. 2009). Returns the wavelength and its probability density.
+pub fn sample_wavelength(u: f64) -> (f64, f64) {
+    let lambda = 538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh();
+    let pdf = 0.003939804 / (0.0072 * (lambda - 538.0)).cosh().powi(2);
+    (lambda, pdf)
+}
+
+// Weight converting the radiance of the path which carried the wavelength back to RGB. The RGB radiance is taken as
+// the spectrum of the same color and its value at the wavelength is projected to RGB through the color matching
+// functions. Paths through non-dispersive materials average to the RGB radiance over many wavelengths.
+pub fn rgb_weight(lambda: f64, pdf: f64) -> Color {
+    let rgb = xyz_to_rgb(cie_xyz(lambda));
+    let integral = *RGB_INTEGRAL;
+    Color::new(rgb.x() / integral.x(), rgb.y() / integral.y(), rgb.z() / integral.z()) / pdf
+}
+
+// Refractive index varying by the wavelength. Wavelengths are in micrometers in the formulas.
+#[derive(Clone, Copy)]
+pub enum Dispersion {
+    // n = a + b / λ²
+    Cauchy { a: f64, b: f64 },
+    // n² = 1 + Σ b_i λ² / (λ² - c_i)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Dispersion {
+    // Common borosilicate crown glass
+    pub const BK7: Self = Self::Sellmeier {
+        b: [1.03961212, 0.231792344, 1.01046945],
+        c: [0.00600069867, 0.0200179144, 103.560653],
+    };
+    // Dense flint glass which disperses much more than crown glass
+    pub const SF11: Self = Self::Sellmeier {
+        b: [1.73759695, 0.313747346, 1.89878101],
+        c: [0.013188707, 0.0623068142, 155.23629],
+    };
+
+    // Refractive index at the wavelength in nanometers
+    pub fn index(&self, lambda: f64) -> f64 {
+        let l2 = (lambda / 1000.0).powi(2);
+        match self {
+            Self::Cauchy { a, b } => a + b / l2,
+            Self::Sellmeier { b, c } => (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>()).sqrt(),
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use std::cell::Cell;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+// Work done while rendering
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Counters {
+    pub paths: u64,           // Primary rays traced by the integrator
+    pub rays: u64,            // Ray segments of all paths
+    pub node_tests: u64,      // Bounding box tests of BVH nodes
+    pub primitive_tests: u64, // Intersection tests of objects in BVH leaves
+}
+
+impl Counters {
+    const ZERO: Self = Self {
+        paths: 0,
+        rays: 0,
+        node_tests: 0,
+        primitive_tests: 0,
+    };
+
+    fn merge(&mut self, other: &Self) {
+        self.paths += other.paths;
+        self.rays += other.rays;
+        self.node_tests += other.node_tests;
+        self.primitive_tests += other.primitive_tests;
+    }
+}
+
+thread_local! {
+    // Each thread counts its own work so that threads never contend on shared counters while rendering
+    static LOCAL: Cell<Counters> = const { Cell::new(Counters::ZERO) };
+}
+
+// Counters of all threads flushed by `total`
+static TOTAL: Mutex<Counters> = Mutex::new(Counters::ZERO);
+
+// Nanoseconds spent on building BVHs
+static BUILD_NANOS: AtomicU64 = AtomicU64::new(0);
+
+pub fn add(f: impl FnOnce(&mut Counters)) {
+    LOCAL.with(|c| {
+        let mut counters = c.get();
+        f(&mut counters);
+        c.set(counters);
+    });
+}
+
+// Counters of the current thread so far
+pub fn local() -> Counters {
+    LOCAL.with(Cell::get)
+}
+
+fn flush() {
+    let counters = LOCAL.with(|c| c.replace(Counters::ZERO));
+    TOTAL.lock().unwrap().merge(&counters);
+}
+
+// Sum of the counters of the current thread and all threads of the global thread pool
+pub fn total() -> Counters {
+    rayon::broadcast(|_| flush());
+    flush();
+    *TOTAL.lock().unwrap()
+}
+
+// Clear the counters of all threads and the build time. Counters keep adding up until this is called, so call it
+// before each render which is reported separately.
+pub fn reset() {
+    let clear = || LOCAL.with(|c| c.set(Counters::ZERO));
+    rayon::broadcast(|_| clear());
+    clear();
+    *TOTAL.lock().unwrap() = Counters::ZERO;
+    BUILD_NANOS.store(0, Ordering::Relaxed);
+}
+
+pub fn add_build_time(time: Duration) {
+    BUILD_NANOS.fetch_add(time.as_nanos() as u64, Ordering::Relaxed);
+}
+
+pub fn build_time() -> Duration {
+    Duration::from_nanos(BUILD_NANOS.load(Ordering::Relaxed))
+}
+
+// Summary of a render
+pub struct Report {
+    pub counters: Counters,
+    pub build_time: Duration,
+    pub render_time: Duration,
+}
+
+impl Report {
+    pub fn new(render_time: Duration) -> Self {
+        Self {
+            counters: total(),
+            build_time: build_time(),
+            render_time,
+        }
+    }
+
+    fn per_path(&self, n: u64) -> f64 {
+        n as f64 / self.counters.paths.max(1) as f64
+    }
+
+    fn per_ray(&self, n: u64) -> f64 {
+        n as f64 / self.counters.rays.max(1) as f64
+    }
+
+    fn rays_per_sec(&self) -> f64 {
+        self.counters.rays as f64 / self.render_time.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+
+    pub fn text(&self) -> String {
+        let c = &self.counters;
+        format!(
+            "Paths:                {}
+Rays:                 {}
+Average path length:  {:.3}
+BVH node tests:       {} ({:.2} per ray)
+Primitive tests:      {} ({:.2} per ray)
+BVH build time:       {:.2?}
+Render time:          {:.2?}
+Rays per second:      {:.0}",
+            c.paths,
+            c.rays,
+            self.per_path(c.rays),
+            c.node_tests,
+            self.per_ray(c.node_tests),
+            c.primitive_tests,
+            self.per_ray(c.primitive_tests),
+            self.build_time,
+            self.render_time,
+            self.rays_per_sec(),
+        )
+    }
+
+    pub fn json(&self) -> String {
+        let c = &self.counters;
+        let fields = [
+            ("paths", c.paths.to_string()),
+            ("rays", c.rays.to_string()),
+            ("average_path_length", self.per_path(c.rays).to_string()),
+            ("node_tests", c.node_tests.to_string()),
+            ("node_tests_per_ray", self.per_ray(c.node_tests).to_string()),
+            ("primitive_tests", c.primitive_tests.to_string()),
+            ("primitive_tests_per_ray", self.per_ray(c.primitive_tests).to_string()),
+            ("build_seconds", self.build_time.as_secs_f64().to_string()),
+            ("render_seconds", self.render_time.as_secs_f64().to_string()),
+            ("rays_per_second", self.rays_per_sec().to_string()),
+        ];
+        let fields: Vec<_> = fields.iter().map(|(k, v)| format!("\"{k}\":{v}")).collect();
+        format!("{{{}}}", fields.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reset_clears_totals() {
+        add(|c| c.paths += 3);
+        rayon::broadcast(|_| add(|c| c.rays += 1));
+        assert!(total().paths >= 3);
+
+        reset();
+        add(|c| c.paths += 1);
+        let counters = total();
+        assert_eq!((counters.paths, counters.rays), (1, 0));
+    }
+}
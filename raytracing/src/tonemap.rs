@@ -0,0 +1,44 @@
+use crate::vec3::Color;
+
+// Operator compressing the linear radiance into the displayable range [0, 1]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ToneMap {
+    // Clip each component at 1. Highlights are blown out
+    Clamp,
+    // L / (1 + L) on the luminance, which keeps the hue. See "Photographic Tone Reproduction for Digital Images"
+    Reinhard,
+    // Krzysztof Narkowicz's fit of the ACES filmic curve applied to each component
+    Aces,
+}
+
+impl ToneMap {
+    pub fn apply(self, c: Color) -> Color {
+        match self {
+            Self::Clamp => c,
+            Self::Reinhard => c / (1.0 + c.luminance().max(0.0)),
+            Self::Aces => {
+                let f = |x: f64| {
+                    let x = x.max(0.0);
+                    x * (2.51 * x + 0.03) / (x * (2.43 * x + 0.59) + 0.14)
+                };
+                Color::new(f(c.x()), f(c.y()), f(c.z()))
+            }
+        }
+    }
+}
+
+// sRGB transfer function (IEC 61966-2-1) from the linear component
+fn linear_to_srgb(x: f64) -> f64 {
+    if x <= 0.0031308 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Convert the linear color to 8-bit sRGB. The color is scaled by 2^exposure before tone mapping.
+pub fn to_rgb(c: Color, exposure: f64, tone_map: ToneMap) -> (u8, u8, u8) {
+    let c = tone_map.apply(c * exposure.exp2());
+    let quantize = |x: f64| (255.0 * linear_to_srgb(x.clamp(0.0, 1.0))).round() as u8;
+    (quantize(c.x()), quantize(c.y()), quantize(c.z()))
+}
@@ -0,0 +1,280 @@
+use crate::aabb::Aabb;
+use crate::vec3::{Point3, Vec3};
+
+// Row-major 3x4 matrix of an affine transformation. The last row of 4x4 matrix is always (0, 0, 0, 1).
+type Matrix = [[f64; 4]; 3];
+
+const IDENTITY: Matrix = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0]];
+
+fn mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 4]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, v) in row.iter_mut().enumerate() {
+            *v = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+        row[3] += a[i][3];
+    }
+    m
+}
+
+// Affine transformation with its inverse. The inverse is built together with the transformation so that it never
+// needs a general matrix inversion.
+#[derive(Clone, Copy)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Transform {
+    pub fn translate(offset: Vec3) -> Self {
+        let (x, y, z) = (offset.x(), offset.y(), offset.z());
+        Self {
+            m: [[1.0, 0.0, 0.0, x], [0.0, 1.0, 0.0, y], [0.0, 0.0, 1.0, z]],
+            inv: [[1.0, 0.0, 0.0, -x], [0.0, 1.0, 0.0, -y], [0.0, 0.0, 1.0, -z]],
+        }
+    }
+
+    pub fn scale(factor: Vec3) -> Self {
+        let (x, y, z) = (factor.x(), factor.y(), factor.z());
+        Self {
+            m: [[x, 0.0, 0.0, 0.0], [0.0, y, 0.0, 0.0], [0.0, 0.0, z, 0.0]],
+            inv: [
+                [1.0 / x, 0.0, 0.0, 0.0],
+                [0.0, 1.0 / y, 0.0, 0.0],
+                [0.0, 0.0, 1.0 / z, 0.0],
+            ],
+        }
+    }
+
+    // Rotation around the axis by the angle in degrees. The inverse of a rotation matrix is its transpose.
+    pub fn rotate(axis: Vec3, degrees: f64) -> Self {
+        let a = axis.unit();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1.0 - cos;
+        let r = [
+            [t * x * x + cos, t * x * y - sin * z, t * x * z + sin * y],
+            [t * x * y + sin * z, t * y * y + cos, t * y * z - sin * x],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos],
+        ];
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] = r[i][j];
+                inv[j][i] = r[i][j];
+            }
+        }
+        Self { m, inv }
+    }
+
+    // Apply `self` at first and then apply `next`
+    pub fn then(&self, next: &Self) -> Self {
+        Self {
+            m: mul(&next.m, &self.m),
+            inv: mul(&self.inv, &next.inv),
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn point(&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        Point3::new(
+            m[0][0] * p.x() + m[0][1] * p.y() + m[0][2] * p.z() + m[0][3],
+            m[1][0] * p.x() + m[1][1] * p.y() + m[1][2] * p.z() + m[1][3],
+            m[2][0] * p.x() + m[2][1] * p.y() + m[2][2] * p.z() + m[2][3],
+        )
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        Vec3::new(
+            m[0][0] * v.x() + m[0][1] * v.y() + m[0][2] * v.z(),
+            m[1][0] * v.x() + m[1][1] * v.y() + m[1][2] * v.z(),
+            m[2][0] * v.x() + m[2][1] * v.y() + m[2][2] * v.z(),
+        )
+    }
+
+    // Normal vectors are transformed by the inverse transpose matrix to keep them perpendicular to the surface.
+    // Note that the result is not normalized.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        let i = &self.inv;
+        Vec3::new(
+            i[0][0] * n.x() + i[1][0] * n.y() + i[2][0] * n.z(),
+            i[0][1] * n.x() + i[1][1] * n.y() + i[2][1] * n.z(),
+            i[0][2] * n.x() + i[1][2] * n.y() + i[2][2] * n.z(),
+        )
+    }
+
+    // The bounding box containing all 8 transformed corners of the box
+    pub fn bbox(&self, bbox: &Aabb) -> Aabb {
+        let (x, y, z) = (bbox.x(), bbox.y(), bbox.z());
+        let mut min = Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Point3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for px in [x.min(), x.max()] {
+            for py in [y.min(), y.max()] {
+                for pz in [z.min(), z.max()] {
+                    let p = self.point(&Point3::new(px, py, pz));
+                    for i in 0..3 {
+                        min[i] = min[i].min(p[i]);
+                        max[i] = max[i].max(p[i]);
+                    }
+                }
+            }
+        }
+        Aabb::from_extrema(min, max)
+    }
+}
+
+// Unit quaternion (w, x, y, z) of a rotation
+#[derive(Clone, Copy)]
+struct Quaternion([f64; 4]);
+
+impl Quaternion {
+    fn from_axis_angle(axis: Vec3, degrees: f64) -> Self {
+        let a = axis.unit();
+        let (sin, cos) = (degrees.to_radians() / 2.0).sin_cos();
+        Self([cos, sin * a.x(), sin * a.y(), sin * a.z()])
+    }
+
+    // Rotation matrix of the unit quaternion
+    fn to_matrix(self) -> [[f64; 3]; 3] {
+        let [w, x, y, z] = self.0;
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+
+    fn dot(&self, other: &Self) -> f64 {
+        self.0.iter().zip(&other.0).map(|(a, b)| a * b).sum()
+    }
+
+    // Spherical linear interpolation along the shorter arc
+    fn slerp(&self, other: &Self, t: f64) -> Self {
+        let mut cos = self.dot(other);
+        let mut to = other.0;
+        if cos < 0.0 {
+            cos = -cos;
+            to = to.map(|c| -c);
+        }
+        let (a, b) = if cos > 0.9995 {
+            // Nearly the same rotation. Fall back to the linear interpolation to avoid dividing by zero
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            (((1.0 - t) * theta).sin() / theta.sin(), (t * theta).sin() / theta.sin())
+        };
+        let q: [f64; 4] = std::array::from_fn(|i| a * self.0[i] + b * to[i]);
+        let len = q.iter().map(|c| c * c).sum::<f64>().sqrt();
+        Self(q.map(|c| c / len))
+    }
+}
+
+// Transformation decomposed into scale, rotation and translation, which are applied in this order. Unlike `Transform`
+// it can be interpolated, so it describes the motion of objects over time.
+#[derive(Clone, Copy)]
+pub struct Pose {
+    scale: Vec3,
+    rotation: Quaternion,
+    translate: Vec3,
+}
+
+impl Pose {
+    pub fn new(scale: Vec3, axis: Vec3, degrees: f64, translate: Vec3) -> Self {
+        let rotation = Quaternion::from_axis_angle(axis, degrees);
+        Self {
+            scale,
+            rotation,
+            translate,
+        }
+    }
+
+    pub fn translate(offset: Vec3) -> Self {
+        Self::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.0, 1.0, 0.0), 0.0, offset)
+    }
+
+    // Build the matrices directly instead of composing `Transform`s since this is evaluated on every hit test of
+    // moving objects. The inverse scales the transposed rotation and undoes the translation.
+    pub fn transform(&self) -> Transform {
+        let r = self.rotation.to_matrix();
+        let (s, t) = (self.scale, self.translate);
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+        for i in 0..3 {
+            for j in 0..3 {
+                m[i][j] = r[i][j] * s[j];
+                inv[i][j] = r[j][i] / s[i];
+            }
+            m[i][3] = t[i];
+        }
+        for row in &mut inv {
+            row[3] = -(0..3).map(|j| row[j] * t[j]).sum::<f64>();
+        }
+        Transform { m, inv }
+    }
+
+    // Pose at `t` between `self` (t = 0) and `other` (t = 1)
+    pub fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            scale: self.scale + t * (other.scale - self.scale),
+            rotation: self.rotation.slerp(&other.rotation, t),
+            translate: self.translate + t * (other.translate - self.translate),
+        }
+    }
+
+    // Angle in radians of the rotation from `self` to `other`
+    pub fn angle_to(&self, other: &Self) -> f64 {
+        2.0 * self.rotation.dot(&other.rotation).abs().min(1.0).acos()
+    }
+
+    // Largest factor of the scale
+    pub fn max_scale(&self) -> f64 {
+        self.scale.x().abs().max(self.scale.y().abs()).max(self.scale.z().abs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pose_matches_composed_transforms() {
+        let (scale, axis, translate) = (
+            Vec3::new(1.0, 2.0, -0.5),
+            Vec3::new(1.0, 2.0, 3.0),
+            Vec3::new(4.0, -5.0, 6.0),
+        );
+        let pose = Pose::new(scale, axis, 70.0, translate).transform();
+        let composed = Transform::scale(scale)
+            .then(&Transform::rotate(axis, 70.0))
+            .then(&Transform::translate(translate));
+        let p = Point3::new(0.3, -1.2, 2.5);
+        for (a, b) in [
+            (pose.point(&p), composed.point(&p)),
+            (pose.inverse().point(&p), composed.inverse().point(&p)),
+            (pose.normal(&p), composed.normal(&p)),
+        ] {
+            assert!((a - b).length() < 1e-12, "{a:?} != {b:?}");
+        }
+    }
+}
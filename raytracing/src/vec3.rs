@@ -1,7 +1,8 @@
-use rand::random_range;
+use crate::rng::SampleRng;
+use rand::Rng;
 use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Range, Sub, SubAssign};
 
-#[derive(Default, Clone, Copy, PartialEq)]
+#[derive(Default, Clone, Copy, PartialEq, Debug)]
 pub struct Vec3([f64; 3]);
 
 impl Vec3 {
@@ -9,16 +10,16 @@ impl Vec3 {
         Self([x, y, z])
     }
 
-    pub fn random(range: Range<f64>) -> Self {
-        let x = random_range(range.clone());
-        let y = random_range(range.clone());
-        let z = random_range(range);
+    pub fn random(rng: &mut SampleRng, range: Range<f64>) -> Self {
+        let x = rng.random_range(range.clone());
+        let y = rng.random_range(range.clone());
+        let z = rng.random_range(range);
         Self::new(x, y, z)
     }
 
-    pub fn random_unit() -> Self {
+    pub fn random_unit(rng: &mut SampleRng) -> Self {
         loop {
-            let p = Self::random(-1.0..1.0);
+            let p = Self::random(rng, -1.0..1.0);
             let l = p.length_squared();
             // Note: When the random vector is inside the unit sphere
             // Note: Ensure `l.sqrt()` doesn't overflow to 0.0 by checking machine epsilon
@@ -28,10 +29,10 @@ impl Vec3 {
         }
     }
 
-    pub fn random_in_unit_circle() -> Self {
+    pub fn random_in_unit_circle(rng: &mut SampleRng) -> Self {
         loop {
-            let x = random_range(-1.0..1.0);
-            let y = random_range(-1.0..1.0);
+            let x = rng.random_range(-1.0..1.0);
+            let y = rng.random_range(-1.0..1.0);
             let p = Self::new(x, y, 0.0);
             if p.length_squared() < 1.0 {
                 return p;
@@ -92,6 +93,11 @@ impl Vec3 {
         r_out_perpendicular + r_out_parallel
     }
 
+    // Relative luminance of the linear RGB color
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.x() + 0.7152 * self.y() + 0.0722 * self.z()
+    }
+
     pub const ZERO: Self = Self::new(0.0, 0.0, 0.0);
 }
 
@@ -221,3 +227,50 @@ impl DivAssign for Vec3 {
 
 pub type Point3 = Vec3;
 pub type Color = Vec3;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-9, "{a:?} != {b:?}");
+    }
+
+    #[test]
+    fn refract_normal_incidence() {
+        // The ray perpendicular to the surface is not bent
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let refracted = Vec3::new(0.0, -1.0, 0.0).refract(&normal, 1.0 / 1.5);
+        assert_close(refracted, Vec3::new(0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn refract_same_index() {
+        let dir = Vec3::new(1.0, -2.0, 0.5).unit();
+        assert_close(dir.refract(&Vec3::new(0.0, 1.0, 0.0), 1.0), dir);
+    }
+
+    #[test]
+    fn refract_follows_snells_law() {
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        for degrees in [10.0f64, 30.0, 45.0, 60.0, 80.0] {
+            let (sin, cos) = degrees.to_radians().sin_cos();
+            let incident = Vec3::new(sin, -cos, 0.0);
+            let refracted = incident.refract(&normal, 1.0 / 1.5);
+            // sin(θ') = sin(θ) / 1.5 on the same side of the normal, and the direction keeps unit length
+            assert!((refracted.length() - 1.0).abs() < 1e-9);
+            assert!((refracted.x() - sin / 1.5).abs() < 1e-9);
+            assert!(refracted.y() < 0.0);
+            assert_eq!(refracted.z(), 0.0);
+        }
+    }
+
+    #[test]
+    fn refract_is_reversible() {
+        let normal = Vec3::new(0.0, 0.0, 1.0);
+        let incident = Vec3::new(0.6, 0.0, -0.8);
+        let inside = incident.refract(&normal, 1.0 / 1.5);
+        // Going out of the glass through the opposite face restores the direction
+        assert_close(inside.refract(&normal, 1.5), incident);
+    }
+}
@@ -0,0 +1,135 @@
+use crate::bvh::{AnyObject, Bvh};
+use crate::environment::EnvironmentMap;
+use crate::interval::Interval;
+use crate::object::{Hit, Hittable, Light};
+use crate::ray::Ray;
+use crate::rng::SampleRng;
+use crate::vec3::{Color, Point3, Vec3};
+use rand::Rng;
+use std::sync::Arc;
+
+// Color of rays which hit nothing
+#[derive(Clone)]
+pub enum Background {
+    // Linear gradient from white to sky blue
+    Gradient,
+    Solid(Color),
+    // HDR image surrounding the scene. It lights the scene and is sampled like lights.
+    Environment(Arc<EnvironmentMap>),
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Self::Gradient => {
+                let u = ray.direction().unit();
+                let a = 0.5 * (u.y() + 1.0);
+                (1.0 - a) * Color::new(1.0, 1.0, 1.0) + a * Color::new(0.5, 0.7, 1.0)
+            }
+            Self::Solid(color) => *color,
+            Self::Environment(map) => map.color(ray.direction()),
+        }
+    }
+
+    // The environment map sampled towards its bright directions
+    fn sampled(&self) -> Option<&EnvironmentMap> {
+        match self {
+            Self::Environment(map) if map.is_sampled() => Some(map),
+            _ => None,
+        }
+    }
+}
+
+// Objects which are sampled directly as light sources. Note that they must be added to the BVH separately.
+#[derive(Default, Clone)]
+pub struct Lights {
+    objects: Vec<Arc<dyn Light>>,
+}
+
+impl Lights {
+    pub fn add(&mut self, light: Arc<dyn Light>) {
+        self.objects.push(light);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
+    // Lights are chosen uniformly so the PDF is the average of their PDFs
+    pub fn pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        let sum: f64 = self.objects.iter().map(|o| o.pdf_value(origin, direction)).sum();
+        sum / self.objects.len() as f64
+    }
+
+    pub fn random(&self, origin: &Point3, rng: &mut SampleRng) -> Vec3 {
+        let idx = rng.random_range(0..self.objects.len());
+        self.objects[idx].random(origin, rng)
+    }
+}
+
+pub struct World {
+    pub objects: Bvh,
+    pub unbounded: Vec<AnyObject>, // Objects without finite bounding boxes such as planes. They are tested one by one
+    pub lights: Lights,
+    pub background: Background,
+}
+
+impl World {
+    pub fn new(objects: Bvh) -> Self {
+        Self {
+            objects,
+            unbounded: vec![],
+            lights: Lights::default(),
+            background: Background::Gradient,
+        }
+    }
+
+    // World for rays whose time is in the interval, or `None` when nothing moves and this world can be used as is.
+    // See `Bvh::during`.
+    pub fn during(&self, time: Interval) -> Option<Self> {
+        Some(Self {
+            objects: self.objects.during(time)?,
+            unbounded: self.unbounded.clone(),
+            lights: self.lights.clone(),
+            background: self.background.clone(),
+        })
+    }
+
+    // The closest hit among the objects in the BVH and the unbounded objects
+    pub fn hit(&self, ray: &Ray, mut time: Interval, rng: &mut SampleRng) -> Option<Hit<'_>> {
+        let mut closest = self.objects.hit(ray, time, rng);
+        if let Some(hit) = &closest {
+            time.upper_bound(hit.time);
+        }
+        for object in &self.unbounded {
+            if let Some(hit) = object.hit(ray, time, rng) {
+                time.upper_bound(hit.time);
+                closest = Some(hit);
+            }
+        }
+        closest
+    }
+
+    // Whether there is anything to sample directly: lights or the environment map
+    pub fn has_lights(&self) -> bool {
+        !self.lights.is_empty() || self.background.sampled().is_some()
+    }
+
+    // When both lights and the environment map exist, each of them is chosen with half probability
+    pub fn light_pdf_value(&self, origin: &Point3, direction: &Vec3) -> f64 {
+        match (self.lights.is_empty(), self.background.sampled()) {
+            (false, None) => self.lights.pdf_value(origin, direction),
+            (true, Some(env)) => env.pdf_value(direction),
+            (false, Some(env)) => 0.5 * self.lights.pdf_value(origin, direction) + 0.5 * env.pdf_value(direction),
+            (true, None) => 0.0,
+        }
+    }
+
+    // Random direction from the origin towards lights or the environment map
+    pub fn random_light(&self, origin: &Point3, rng: &mut SampleRng) -> Vec3 {
+        match self.background.sampled() {
+            Some(env) if self.lights.is_empty() || rng.random_bool(0.5) => env.random(rng),
+            _ => self.lights.random(origin, rng),
+        }
+    }
+}
@@ -0,0 +1,83 @@
+// Distributed rendering over loopback TCP. Workers run on threads of this process and serve tiles like `--listen`.
+
+use raytracing::camera::Camera;
+use raytracing::distributed::{self, Worker};
+use raytracing::scene::Scene;
+use raytracing::world::World;
+use std::fs;
+use std::io::{BufReader, BufWriter, ErrorKind, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+// The coordinator and the workers build the same camera and scene from the same options
+fn camera() -> (Camera, World) {
+    let mut cam = Camera::new().unwrap();
+    cam.image_width = 48;
+    cam.image_height = 27;
+    cam.samples_per_pixel = 8;
+    cam.tile_size = 8;
+    let world = Scene::Demo.world(&mut cam);
+    (cam, world)
+}
+
+#[test]
+fn failed_worker_is_replaced() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap().to_string();
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    let (distributed, local) = (dir.join("distributed.ppm"), dir.join("local.ppm"));
+    let failed = AtomicBool::new(false);
+
+    let (mut cam, world) = camera();
+    thread::scope(|s| {
+        s.spawn(|| {
+            // The first worker dies in the middle of sending its first result
+            {
+                let (stream, _) = listener.accept().unwrap();
+                let mut input = BufReader::new(stream.try_clone().unwrap());
+                distributed::read_args(&mut input).unwrap();
+                let tile = distributed::read_request(&mut input).unwrap().unwrap();
+                (&stream).write_all(&tile.x.to_le_bytes()).unwrap();
+                failed.store(true, Ordering::SeqCst);
+            }
+            // The others render every tile they are given
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                s.spawn(move || {
+                    let (mut cam, world) = camera();
+                    let mut input = BufReader::new(stream.try_clone().unwrap());
+                    distributed::read_args(&mut input).unwrap();
+                    cam.serve_tiles(&world, input, BufWriter::new(stream)).unwrap();
+                });
+            }
+        });
+        let workers = (0..3).map(|_| Worker::connect(&addr).unwrap()).collect();
+        cam.render_distributed(&distributed, &world, workers, &[]).unwrap();
+    });
+    assert!(
+        failed.load(Ordering::SeqCst),
+        "the failing worker was never given a tile"
+    );
+
+    let (mut cam, world) = camera();
+    cam.render_parallel(&local, &world).unwrap();
+    assert!(
+        fs::read(&distributed).unwrap() == fs::read(&local).unwrap(),
+        "{distributed:?} differs from {local:?}"
+    );
+}
+
+#[test]
+fn oversized_tile() {
+    // A peer can send any image size, so a whole-image tile of a huge image must be rejected before rendering it
+    let (mut cam, world) = camera();
+    cam.image_width = 70000;
+    cam.image_height = 70000;
+    let request: Vec<u8> = [0u32, 0, 70000, 70000].iter().flat_map(|v| v.to_le_bytes()).collect();
+    let mut output = vec![];
+    let err = cam.serve_tiles(&world, &request[..], &mut output).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput, "{err}");
+    assert!(output.is_empty());
+}
@@ -0,0 +1,271 @@
+// Golden image tests. Small renders of each scene are compared with the reference images in `tests/golden`. Run with
+// `UPDATE_GOLDEN=1` to rewrite the references after intended changes of the output, and check the new images by eye.
+
+use raytracing::camera::Camera;
+use raytracing::scene::Scene;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 36;
+const SAMPLES: u32 = 64;
+
+// Read a plain PPM (P3) image written by `Camera`
+fn read_ppm(path: &Path) -> (u32, u32, Vec<u8>) {
+    let text = fs::read_to_string(path).unwrap_or_else(|e| panic!("cannot read {path:?}: {e}"));
+    let mut tokens = text.split_whitespace();
+    assert_eq!(tokens.next(), Some("P3"), "{path:?} is not a plain PPM");
+    let mut next = || tokens.next().unwrap().parse::<u32>().unwrap();
+    let (width, height, _max) = (next(), next(), next());
+    let values = (0..width * height * 3).map(|_| next() as u8).collect();
+    (width, height, values)
+}
+
+// Side length of the blocks whose means are compared
+const BLOCK: u32 = 4;
+
+// Bounds of the RMSE of the pixels and of the means of the blocks. Averaging the blocks removes most of the noise, so
+// the latter catches small shifts of brightness or color hidden in the noise of each pixel. The bounds are about 1.5
+// times the errors between renders with different seeds, so that changes of random sampling pass. They must also be at
+// most a third of the errors of a flat grey image of the mean of the reference, or the test would not catch much. Scenes
+// whose noise does not allow that are rendered with more samples.
+struct Tolerance {
+    pixel: f64,
+    block: f64,
+}
+
+// Channel values normalized to [0, 1]
+fn normalize(values: &[u8]) -> Vec<f64> {
+    values.iter().map(|&v| v as f64 / 255.0).collect()
+}
+
+// Mean channel values of the blocks of the image
+fn block_means(width: u32, height: u32, values: &[f64]) -> Vec<f64> {
+    let mut means = vec![];
+    for by in 0..height / BLOCK {
+        for bx in 0..width / BLOCK {
+            for c in 0..3 {
+                let mut sum = 0.0;
+                for y in by * BLOCK..(by + 1) * BLOCK {
+                    for x in bx * BLOCK..(bx + 1) * BLOCK {
+                        sum += values[((y * width + x) * 3 + c) as usize];
+                    }
+                }
+                means.push(sum / (BLOCK * BLOCK) as f64);
+            }
+        }
+    }
+    means
+}
+
+fn rmse(a: &[f64], b: &[f64]) -> f64 {
+    let sum: f64 = a.iter().zip(b).map(|(a, b)| (a - b).powi(2)).sum();
+    (sum / a.len() as f64).sqrt()
+}
+
+// RMS distance of the channels from the grey of their pixels
+fn colorfulness(values: &[f64]) -> f64 {
+    let grey: Vec<f64> = values
+        .chunks(3)
+        .flat_map(|p| [p.iter().sum::<f64>() / 3.0; 3])
+        .collect();
+    rmse(values, &grey)
+}
+
+// Render the scene with the options of the golden images changed by `configure` and return the path of the output
+fn render(name: &str, scene: Scene, configure: impl FnOnce(&mut Camera)) -> PathBuf {
+    let mut cam = Camera::new().unwrap();
+    cam.image_width = WIDTH;
+    cam.image_height = HEIGHT;
+    cam.samples_per_pixel = SAMPLES;
+    let world = scene.world(&mut cam);
+    configure(&mut cam);
+
+    let output = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join(format!("{name}.ppm"));
+    cam.render_parallel(&output, &world).unwrap();
+    output
+}
+
+// Render the scene, compare it with the reference and return the normalized channel values of the output
+fn check(name: &str, scene: Scene, tolerance: Tolerance, configure: impl FnOnce(&mut Camera)) -> Vec<f64> {
+    let output = render(name, scene, configure);
+    let reference = Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/golden/{name}.ppm"));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::copy(&output, &reference).unwrap();
+    }
+
+    let (width, height, actual) = read_ppm(&output);
+    let (ref_width, ref_height, expected) = read_ppm(&reference);
+    assert_eq!(
+        (width, height),
+        (ref_width, ref_height),
+        "size of {name} differs from the reference"
+    );
+    let (actual, expected) = (normalize(&actual), normalize(&expected));
+
+    let flat = vec![expected.iter().sum::<f64>() / expected.len() as f64; expected.len()];
+    let (flat_pixel, flat_block) = (
+        rmse(&flat, &expected),
+        rmse(
+            &block_means(width, height, &flat),
+            &block_means(width, height, &expected),
+        ),
+    );
+    assert!(
+        tolerance.pixel * 3.0 <= flat_pixel && tolerance.block * 3.0 <= flat_block,
+        "tolerance of {name} is too loose: a flat image scores {flat_pixel:.4} and {flat_block:.4}",
+    );
+
+    let error = rmse(&actual, &expected);
+    assert!(
+        error <= tolerance.pixel,
+        "{name} differs from the reference: RMSE of pixels {error:.4} > {}. See {output:?}",
+        tolerance.pixel,
+    );
+    let error = rmse(
+        &block_means(width, height, &actual),
+        &block_means(width, height, &expected),
+    );
+    assert!(
+        error <= tolerance.block,
+        "{name} differs from the reference: RMSE of blocks {error:.4} > {}. See {output:?}",
+        tolerance.block,
+    );
+    actual
+}
+
+#[test]
+fn demo() {
+    check(
+        "demo",
+        Scene::Demo,
+        Tolerance {
+            pixel: 0.04,
+            block: 0.01,
+        },
+        |_| {},
+    );
+}
+
+#[test]
+fn checker() {
+    check(
+        "checker",
+        Scene::Checker,
+        Tolerance {
+            pixel: 0.055,
+            block: 0.015,
+        },
+        |_| {},
+    );
+}
+
+#[test]
+fn instances() {
+    check(
+        "instances",
+        Scene::Instances,
+        Tolerance {
+            pixel: 0.025,
+            block: 0.006,
+        },
+        |_| {},
+    );
+}
+
+#[test]
+fn fog() {
+    check(
+        "fog",
+        Scene::Fog,
+        Tolerance {
+            pixel: 0.052,
+            block: 0.013,
+        },
+        |_| {},
+    );
+}
+
+#[test]
+fn lights() {
+    check(
+        "lights",
+        Scene::Lights,
+        Tolerance {
+            pixel: 0.031,
+            block: 0.008,
+        },
+        |cam| cam.samples_per_pixel = 256,
+    );
+}
+
+#[test]
+fn materials() {
+    check(
+        "materials",
+        Scene::Materials,
+        Tolerance {
+            pixel: 0.031,
+            block: 0.0075,
+        },
+        |cam| cam.samples_per_pixel = 512,
+    );
+}
+
+#[test]
+fn shapes() {
+    check(
+        "shapes",
+        Scene::Shapes,
+        Tolerance {
+            pixel: 0.016,
+            block: 0.004,
+        },
+        |cam| cam.samples_per_pixel = 512,
+    );
+}
+
+#[test]
+fn motion() {
+    check(
+        "motion",
+        Scene::Motion,
+        Tolerance {
+            pixel: 0.026,
+            block: 0.008,
+        },
+        |_| {},
+    );
+}
+
+#[test]
+fn dispersion() {
+    // Spectral rendering is much noisier than the others, so only the middle of the image with both glasses is rendered
+    // with many samples
+    let crop = |cam: &mut Camera| {
+        cam.image_width = WIDTH / 2;
+        cam.image_height = HEIGHT / 2;
+        cam.vfov /= 2.0;
+    };
+    let spectral = check(
+        "dispersion",
+        Scene::Dispersion,
+        Tolerance {
+            pixel: 0.12,
+            block: 0.029,
+        },
+        |cam| {
+            crop(cam);
+            cam.samples_per_pixel = 1024;
+            cam.spectral = true;
+        },
+    );
+
+    // Noise dominates the errors, so a grey render without wavelengths would pass as well. The colors tell them apart
+    let (_, _, grey) = read_ppm(&render("dispersion-grey", Scene::Dispersion, crop));
+    let (spectral, grey) = (colorfulness(&spectral), colorfulness(&normalize(&grey)));
+    assert!(
+        grey < 0.01 && spectral > 0.04,
+        "colorfulness of spectral and non-spectral renders of dispersion are {spectral:.4} and {grey:.4}",
+    );
+}